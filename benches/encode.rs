@@ -0,0 +1,111 @@
+//! Request encoding: arguments converted to `Value` vs borrowed `CallArgs`
+//!
+//! Run with `cargo bench --bench encode`.
+extern crate neovim_lib;
+
+use std::io::{self, BufWriter, Write};
+use std::time::Instant;
+
+use neovim_lib::{encode_message, encode_request, CallArgs, IntoVal, RpcMessage, Value};
+
+fn bench<F: FnMut()>(name: &str, iterations: u32, mut f: F) {
+    // warm up
+    f();
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let per_iter = start.elapsed() / iterations;
+    println!("{:<40} {:>12?}", name, per_iter);
+}
+
+fn write_values<W: Write>(writer: &mut W, method: &str, params: Vec<Value>) {
+    let msg = RpcMessage::RpcRequest {
+        msgid: 1,
+        method: method.to_owned(),
+        params,
+    };
+    encode_message(writer, msg).unwrap();
+}
+
+fn write_args<W: Write>(writer: &mut W, method: &str, params: &CallArgs) {
+    encode_request(writer, 1, method, params).unwrap();
+}
+
+fn set_lines(count: usize, iterations: u32) {
+    let buffer = Value::Ext(0, vec![1]);
+    let lines: Vec<String> = (0..count)
+        .map(|i| format!("{:>6}: the quick brown fox jumps over the lazy dog", i))
+        .collect();
+    let mut writer = BufWriter::new(io::sink());
+
+    // both variants get owned lines, like generated `set_lines` does
+    bench(
+        &format!("set_lines {} lines, values", count),
+        iterations,
+        || {
+            let lines = lines.clone();
+            let params = vec![
+                buffer.clone(),
+                0.into_val(),
+                (-1).into_val(),
+                false.into_val(),
+                lines.into_val(),
+            ];
+            write_values(&mut writer, "nvim_buf_set_lines", params);
+        },
+    );
+    bench(
+        &format!("set_lines {} lines, borrowed", count),
+        iterations,
+        || {
+            let lines = lines.clone();
+            let (start, end, strict) = (0i64, -1i64, false);
+            let mut params = CallArgs::new();
+            params
+                .push(&buffer)
+                .push(&start)
+                .push(&end)
+                .push(&strict)
+                .push(&lines);
+            write_args(&mut writer, "nvim_buf_set_lines", &params);
+        },
+    );
+    // `Session::call_args` doesn't need owned lines at all
+    bench(
+        &format!("set_lines {} lines, borrowed no clone", count),
+        iterations,
+        || {
+            let (start, end, strict) = (0i64, -1i64, false);
+            let mut params = CallArgs::new();
+            params
+                .push(&buffer)
+                .push(&start)
+                .push(&end)
+                .push(&strict)
+                .push(&lines);
+            write_args(&mut writer, "nvim_buf_set_lines", &params);
+        },
+    );
+}
+
+fn command(iterations: u32) {
+    let command = "echo 'hello'";
+    let mut writer = BufWriter::new(io::sink());
+
+    bench("command, values", iterations, || {
+        write_values(&mut writer, "nvim_command", vec![command.into_val()]);
+    });
+    bench("command, borrowed", iterations, || {
+        let mut params = CallArgs::new();
+        params.push(&command);
+        write_args(&mut writer, "nvim_command", &params);
+    });
+}
+
+fn main() {
+    command(1_000_000);
+    set_lines(100, 10_000);
+    set_lines(100_000, 20);
+}
@@ -2,36 +2,68 @@
 
 use neovim::*;
 use rpc::*;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
 
 {% for etype in exttypes %}
-#[derive(PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct {{ etype.name }} {
     code_data: Value,
+    handle: Option<i64>,
 }
 
 impl {{ etype.name }} {
+    /// Wrap value, handle is `None` when value isn't ext handle
     pub fn new(code_data: Value) -> {{ etype.name }} {
-        {{ etype.name }} {
-            code_data,
+        let handle = model::decode_ext_handle(&code_data).map(|(_, handle)| handle);
+        {{ etype.name }} { code_data, handle }
+    }
+
+    /// Same as `new`, but fails when value isn't ext handle
+    pub fn try_new(code_data: Value) -> Result<{{ etype.name }}, String> {
+        match model::decode_ext_handle(&code_data) {
+            Some((_, handle)) => Ok({{ etype.name }} {
+                code_data,
+                handle: Some(handle),
+            }),
+            None => Err(format!("Can't decode {{ etype.name }} handle from {}", code_data)),
         }
     }
 
+    /// Create from handle number, ext type id is taken from `ext_types`
+    pub fn from_handle(ext_types: &ExtTypes, handle: i64) -> {{ etype.name }} {
+        {{ etype.name }}::new(model::encode_ext_handle(ext_types.{{ etype.name|lower }}, handle))
+    }
+
     /// Internal value, that represent type
     pub fn get_value(&self) -> &Value {
         &self.code_data
     }
 
+    /// Numeric handle, decoded from internal value, `None` when value isn't ext handle
+    pub fn handle(&self) -> Option<i64> {
+        self.handle
+    }
+
     {% for f in functions if f.ext and f.name.startswith(etype.prefix) %}
     /// since: {{f.since}}
-    pub fn {{f.name|replace(etype.prefix, '')}}(&self, neovim: &mut Neovim, {{f.argstring}}) -> Result<{{f.return_type.native_type_ret}}, CallError> {
-        neovim.session.call("{{f.name}}",
-                          call_args![self.code_data.clone()
+    {% if 'String' in f.return_type.native_type_ret %}
+    ///
+    /// Fails on invalid utf-8, see `bytes` module for binary safe variant
+    {% endif %}
+    pub fn {{f.name|replace(etype.prefix, '')}}<B: CallBackend>(&self, neovim: &mut B, {{f.argstring}}) -> Result<{{f.return_type.native_type_ret}}, CallError> {
+        neovim.call_args("{{f.name}}",
+                          &encode_args![self.code_data
                           {% if f.parameters|count > 0 %}
                           , {{ f.parameters|map(attribute = "name")|join(", ") }}
                           {% endif %}
                           ])
+                    {% if 'String' in f.return_type.native_type_ret %}
+                    .and_then(try_map_result)
+                    {% else %}
                     .map(map_result)
-                    .map_err(map_generic_error)
+                    {% endif %}
     }
     {% endfor %}
 }
@@ -45,28 +77,72 @@ impl FromVal<Value> for {{ etype.name }} {
     }
 }
 
+impl TryFromVal<Value> for {{ etype.name }} {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        {{ etype.name }}::try_new(val)
+    }
+}
+
 impl <'a> IntoVal<Value> for &'a {{etype.name}} {
     fn into_val(self) -> Value {
         self.code_data.clone()
     }
 }
-{% endfor %}
 
-pub trait NeovimApi {
-    {% for f in functions if not f.ext %}
-    /// since: {{f.since}}
-    fn {{f.name|replace('nvim_', '')}}(&mut self, {{f.argstring}}) -> Result<{{f.return_type.native_type_ret}}, CallError>;
-    {% endfor %}
+impl EncodeArg for {{etype.name}} {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        self.code_data.encode_arg(writer)
+    }
+}
+
+impl PartialEq for {{ etype.name }} {
+    fn eq(&self, other: &{{ etype.name }}) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for {{ etype.name }} {}
+
+impl Hash for {{ etype.name }} {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.handle.hash(state)
+    }
+}
+
+impl PartialOrd for {{ etype.name }} {
+    fn partial_cmp(&self, other: &{{ etype.name }}) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
-impl NeovimApi for Neovim {
+impl Ord for {{ etype.name }} {
+    fn cmp(&self, other: &{{ etype.name }}) -> Ordering {
+        match (self.handle, other.handle) {
+            (None, None) => model::cmp_undecoded(&self.code_data, &other.code_data),
+            (handle, other_handle) => handle.cmp(&other_handle),
+        }
+    }
+}
+{% endfor %}
+
+pub trait NeovimApi: CallBackend {
     {% for f in functions if not f.ext %}
+    /// since: {{f.since}}
+    {% if 'String' in f.return_type.native_type_ret %}
+    ///
+    /// Fails on invalid utf-8, see `bytes` module for binary safe variant
+    {% endif %}
     fn {{f.name|replace('nvim_', '')}}(&mut self, {{f.argstring}}) -> Result<{{f.return_type.native_type_ret}}, CallError> {
-        self.session.call("{{f.name}}",
-                          call_args![{{ f.parameters|map(attribute = "name")|join(", ") }}])
-                    .map(map_result)
-                    .map_err(map_generic_error)
+        self.call_args("{{f.name}}",
+                  &encode_args![{{ f.parameters|map(attribute = "name")|join(", ") }}])
+            {% if 'String' in f.return_type.native_type_ret %}
+            .and_then(try_map_result)
+            {% else %}
+            .map(map_result)
+            {% endif %}
     }
 
     {% endfor %}
 }
+
+impl NeovimApi for Neovim {}
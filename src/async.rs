@@ -2,29 +2,30 @@ use std::marker::PhantomData;
 
 use rmpv::Value;
 
-use neovim;
-use rpc::model::FromVal;
-use session::ClientConnection;
+use neovim::{self, AsyncCallback, CallBackend};
+use rpc::model::TryFromVal;
+use rpc::CancelHandle;
 
-pub struct AsyncCall<'a, R: FromVal<Value>> {
+pub struct AsyncCall<'a, R: TryFromVal<Value>> {
     method: String,
     args: Vec<Value>,
-    client: &'a mut ClientConnection,
-    cb: Option<Box<FnMut(Result<Value, Value>) + Send + 'static>>,
+    backend: &'a mut dyn CallBackend,
+    cb: Option<AsyncCallback>,
     marker: PhantomData<R>,
 }
 
-impl<'a, R: FromVal<Value>> AsyncCall<'a, R> {
-    pub fn new(client: &'a mut ClientConnection, method: String, args: Vec<Value>) -> Self {
+impl<'a, R: TryFromVal<Value>> AsyncCall<'a, R> {
+    pub fn new(backend: &'a mut dyn CallBackend, method: String, args: Vec<Value>) -> Self {
         AsyncCall {
             method,
             args,
-            client,
+            backend,
             cb: None,
             marker: PhantomData,
         }
     }
 
+    /// Set callback, unexpected result is reported as `CallError::GenericError`
     pub fn cb<F>(mut self, cb: F) -> Self
     where
         F: FnOnce(Result<R, neovim::CallError>) + Send + 'static,
@@ -32,29 +33,16 @@ impl<'a, R: FromVal<Value>> AsyncCall<'a, R> {
         let mut cb = Some(cb);
 
         self.cb = Some(Box::new(move |res| {
-            let res = res.map(R::from_val).map_err(neovim::map_generic_error);
+            let res = res.and_then(neovim::try_map_result);
             cb.take().unwrap()(res);
         }));
         self
     }
 
     /// Async call. Call can be made only after event loop begin processing
-    pub fn call(self) {
-        match *self.client {
-            ClientConnection::Child(ref mut client, _) => {
-                client.call_async(self.method, self.args, self.cb)
-            }
-            ClientConnection::Parent(ref mut client) => {
-                client.call_async(self.method, self.args, self.cb)
-            }
-            ClientConnection::Tcp(ref mut client) => {
-                client.call_async(self.method, self.args, self.cb)
-            }
-
-            #[cfg(unix)]
-            ClientConnection::UnixSocket(ref mut client) => {
-                client.call_async(self.method, self.args, self.cb)
-            }
-        };
+    ///
+    /// Returned handle can be used to cancel the call.
+    pub fn call(self) -> CancelHandle {
+        self.backend.call_async(&self.method, self.args, self.cb)
     }
 }
@@ -0,0 +1,148 @@
+//! Binary safe variants of line, variable and eval api
+//!
+//! Buffer may contain any bytes, for example latin1 text.
+//! These functions return raw bytes, `lossy` and `strict` helpers convert them to `String`.
+//! Value, that is neither string nor binary, is reported as `CallError::GenericError`.
+use std::string::FromUtf8Error;
+
+use neovim::{map_result, try_map_result, CallBackend, CallError, Neovim};
+use neovim_api::Buffer;
+use rpc::model;
+use rpc::CallArgs;
+
+/// Convert bytes to string, invalid utf-8 is replaced with `U+FFFD`
+pub fn lossy(bytes: Vec<u8>) -> String {
+    model::utf8_lossy(bytes)
+}
+
+/// Convert bytes to string, fails on invalid utf-8
+pub fn strict(bytes: Vec<u8>) -> Result<String, FromUtf8Error> {
+    String::from_utf8(bytes)
+}
+
+/// Convert lines with `lossy`
+pub fn lines_lossy(lines: Vec<Vec<u8>>) -> Vec<String> {
+    lines.into_iter().map(lossy).collect()
+}
+
+/// Convert lines with `strict`, fails on first invalid line
+pub fn lines_strict(lines: Vec<Vec<u8>>) -> Result<Vec<String>, FromUtf8Error> {
+    lines.into_iter().map(strict).collect()
+}
+
+fn utf8_error(e: FromUtf8Error) -> CallError {
+    CallError::GenericError(format!("Invalid utf-8: {}", e))
+}
+
+/// Byte variants of `NeovimApi` functions
+pub trait NeovimBytesApi: CallBackend {
+    fn get_current_line_bytes(&mut self) -> Result<Vec<u8>, CallError> {
+        self.call_args("nvim_get_current_line", &encode_args![])
+            .and_then(try_map_result)
+    }
+
+    fn set_current_line_bytes(&mut self, line: &[u8]) -> Result<(), CallError> {
+        self.call_args("nvim_set_current_line", &encode_args![line])
+            .map(map_result)
+    }
+
+    fn get_var_bytes(&mut self, name: &str) -> Result<Vec<u8>, CallError> {
+        self.call_args("nvim_get_var", &encode_args![name])
+            .and_then(try_map_result)
+    }
+
+    fn set_var_bytes(&mut self, name: &str, value: &[u8]) -> Result<(), CallError> {
+        self.call_args("nvim_set_var", &encode_args![name, value])
+            .map(map_result)
+    }
+
+    /// Evaluate expression, that returns string
+    fn eval_bytes(&mut self, expr: &str) -> Result<Vec<u8>, CallError> {
+        self.call_args("nvim_eval", &encode_args![expr])
+            .and_then(try_map_result)
+    }
+}
+
+impl NeovimBytesApi for Neovim {}
+
+impl Buffer {
+    pub fn get_lines_bytes<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        start: i64,
+        end: i64,
+        strict_indexing: bool,
+    ) -> Result<Vec<Vec<u8>>, CallError> {
+        let buffer = self.get_value();
+        neovim
+            .call_args(
+                "nvim_buf_get_lines",
+                &encode_args![buffer, start, end, strict_indexing],
+            )
+            .and_then(try_map_result)
+    }
+
+    /// Same as `get_lines_bytes`, invalid utf-8 is replaced with `U+FFFD`
+    pub fn get_lines_lossy<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        start: i64,
+        end: i64,
+        strict_indexing: bool,
+    ) -> Result<Vec<String>, CallError> {
+        self.get_lines_bytes(neovim, start, end, strict_indexing)
+            .map(lines_lossy)
+    }
+
+    /// Same as `get_lines_bytes`, but fails on invalid utf-8
+    pub fn get_lines_strict<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        start: i64,
+        end: i64,
+        strict_indexing: bool,
+    ) -> Result<Vec<String>, CallError> {
+        self.get_lines_bytes(neovim, start, end, strict_indexing)
+            .and_then(|lines| lines_strict(lines).map_err(utf8_error))
+    }
+
+    pub fn set_lines_bytes<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        start: i64,
+        end: i64,
+        strict_indexing: bool,
+        replacement: &[Vec<u8>],
+    ) -> Result<(), CallError> {
+        let buffer = self.get_value();
+        neovim
+            .call_args(
+                "nvim_buf_set_lines",
+                &encode_args![buffer, start, end, strict_indexing, replacement],
+            )
+            .map(map_result)
+    }
+
+    pub fn get_var_bytes<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        name: &str,
+    ) -> Result<Vec<u8>, CallError> {
+        let buffer = self.get_value();
+        neovim
+            .call_args("nvim_buf_get_var", &encode_args![buffer, name])
+            .and_then(try_map_result)
+    }
+
+    pub fn set_var_bytes<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        name: &str,
+        value: &[u8],
+    ) -> Result<(), CallError> {
+        let buffer = self.get_value();
+        neovim
+            .call_args("nvim_buf_set_var", &encode_args![buffer, name, value])
+            .map(map_result)
+    }
+}
@@ -0,0 +1,360 @@
+//! Spawn configured embedded neovim instance
+//!
+//! ```no_run
+//! use neovim_lib::{EmbedOptions, Neovim, NeovimApi};
+//! use std::time::Duration;
+//!
+//! let session = EmbedOptions::new()
+//!     .set_headless(true)
+//!     .set_clean(true)
+//!     .set_isolated(true)
+//!     .set_startup_timeout(Duration::from_secs(10))
+//!     .spawn()
+//!     .unwrap();
+//! let mut nvim = Neovim::new(session);
+//! nvim.command("echo \"Test\"").unwrap();
+//! ```
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use rmpv::Value;
+use tempdir::TempDir;
+
+use neovim::CallError;
+use rpc::handler::{DefaultHandler, Handler, RequestHandler};
+use session::{Session, StderrMode};
+
+type NotifyReceiver = mpsc::Receiver<(String, Vec<Value>)>;
+
+/// Initialization mode of embedded instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitMode {
+    /// Load user configuration as usual
+    Default,
+    /// `-u NONE`, skip user configuration
+    NoConfig,
+    /// `--clean`, skip user configuration and plugins from user directories
+    Clean,
+}
+
+/// Options to spawn embedded neovim instance
+pub struct EmbedOptions {
+    program: PathBuf,
+    embed: bool,
+    headless: bool,
+    init: InitMode,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+    isolated: bool,
+    current_dir: Option<PathBuf>,
+    stderr: StderrMode,
+    startup_timeout: Duration,
+}
+
+impl EmbedOptions {
+    pub fn new() -> EmbedOptions {
+        let program = if cfg!(target_os = "windows") {
+            "nvim.exe"
+        } else {
+            "nvim"
+        };
+
+        EmbedOptions {
+            program: PathBuf::from(program),
+            embed: true,
+            headless: false,
+            init: InitMode::Default,
+            args: Vec::new(),
+            envs: Vec::new(),
+            isolated: false,
+            current_dir: None,
+            stderr: StderrMode::Inherit,
+            startup_timeout: Duration::new(5, 0),
+        }
+    }
+
+    /// Path to neovim executable
+    pub fn set_program<P: AsRef<Path>>(&mut self, program: P) -> &mut Self {
+        self.program = program.as_ref().to_owned();
+        self
+    }
+
+    /// `--embed` argument, enabled by default
+    ///
+    /// Startup handshake needs rpc on stdio, so `spawn` fails when it's disabled,
+    /// unless `--embed` is passed with `arg`.
+    pub fn set_embed(&mut self, embed: bool) -> &mut Self {
+        self.embed = embed;
+        self
+    }
+
+    /// `--headless` argument
+    pub fn set_headless(&mut self, headless: bool) -> &mut Self {
+        self.headless = headless;
+        self
+    }
+
+    /// Which configuration is loaded on startup, `InitMode::Default` by default
+    pub fn set_init_mode(&mut self, init: InitMode) -> &mut Self {
+        self.init = init;
+        self
+    }
+
+    /// Shortcut for `set_init_mode(InitMode::NoConfig)`
+    pub fn set_no_config(&mut self, no_config: bool) -> &mut Self {
+        self.set_init_mode(if no_config {
+            InitMode::NoConfig
+        } else {
+            InitMode::Default
+        })
+    }
+
+    /// Shortcut for `set_init_mode(InitMode::Clean)`
+    pub fn set_clean(&mut self, clean: bool) -> &mut Self {
+        self.set_init_mode(if clean {
+            InitMode::Clean
+        } else {
+            InitMode::Default
+        })
+    }
+
+    /// Add extra argument, passed after generated ones
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Add extra arguments, passed after generated ones
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Override environment variable of child process
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, val: V) -> &mut Self {
+        self.envs
+            .push((key.as_ref().to_owned(), val.as_ref().to_owned()));
+        self
+    }
+
+    /// Use temporary `XDG_CONFIG_HOME`, `XDG_DATA_HOME` and `XDG_STATE_HOME`
+    ///
+    /// Directory is removed when session is dropped.
+    pub fn set_isolated(&mut self, isolated: bool) -> &mut Self {
+        self.isolated = isolated;
+        self
+    }
+
+    /// Working directory of child process
+    pub fn set_current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.current_dir = Some(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Destination for stderr output, inherited by default
+    pub fn set_stderr(&mut self, stderr: StderrMode) -> &mut Self {
+        self.stderr = stderr;
+        self
+    }
+
+    /// Deadline for startup handshake, 5 seconds by default
+    pub fn set_startup_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    /// Spawn neovim and start processing rpc response and notifications
+    pub fn spawn(&self) -> Result<Session> {
+        self.spawn_handler(DefaultHandler())
+    }
+
+    /// Spawn neovim and start processing rpc response and notifications
+    pub fn spawn_handler<H>(&self, handler: H) -> Result<Session>
+    where
+        H: Handler + Send + 'static,
+    {
+        self.spawn_with(|session| session.start_event_loop_handler(handler))
+            .map(|(session, _)| session)
+    }
+
+    /// Spawn neovim and start processing rpc response and notifications
+    pub fn spawn_channel_handler<H>(&self, request_handler: H) -> Result<(Session, NotifyReceiver)>
+    where
+        H: RequestHandler + Send + 'static,
+    {
+        self.spawn_with(|session| session.start_event_loop_channel_handler(request_handler))
+    }
+
+    /// Spawn neovim and start processing rpc response and notifications
+    pub fn spawn_channel(&self) -> Result<(Session, NotifyReceiver)> {
+        self.spawn_channel_handler(DefaultHandler())
+    }
+
+    fn spawn_with<F, T>(&self, start_event_loop: F) -> Result<(Session, T)>
+    where
+        F: FnOnce(&mut Session) -> T,
+    {
+        if !self.embed && !self.args.iter().any(|arg| arg == "--embed") {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Embedded neovim needs --embed argument",
+            ));
+        }
+
+        let tmp_dir = if self.isolated {
+            Some(TempDir::new("neovim-lib")?)
+        } else {
+            None
+        };
+
+        let mut cmd = self.command(tmp_dir.as_ref().map(|dir| dir.path()))?;
+        let mut session = Session::new_child_cmd_stderr(&mut cmd, self.stderr.clone())?;
+        session.tmp_dir = tmp_dir;
+
+        let res = start_event_loop(&mut session);
+
+        let timeout = session.timeout;
+        session.timeout = Some(self.startup_timeout);
+        let started = Instant::now();
+        let handshake = session.call("nvim_get_api_info", vec![]);
+        session.timeout = timeout;
+
+        let err = match handshake {
+            Ok(_) => return Ok((session, res)),
+            Err(err) => err,
+        };
+
+        let status = session.try_wait_child().ok().and_then(|status| status);
+        if status.is_none() {
+            session.kill_child().ok();
+        }
+        session.wait_child().ok();
+
+        let kind = match err {
+            CallError::Disconnected(_) => ErrorKind::BrokenPipe,
+            CallError::GenericError(_) if started.elapsed() >= self.startup_timeout => {
+                ErrorKind::TimedOut
+            }
+            _ => ErrorKind::Other,
+        };
+        let mut msg = format!("Startup handshake failed: {}", err);
+        if let Some(status) = status {
+            let status = format!("nvim exited with {}", status);
+            if !msg.contains(&status) {
+                msg = format!("{}, {}", msg, status);
+            }
+        }
+        Err(Error::new(kind, msg))
+    }
+
+    fn command(&self, tmp_dir: Option<&Path>) -> Result<Command> {
+        let mut cmd = Command::new(&self.program);
+
+        if self.embed {
+            cmd.arg("--embed");
+        }
+        if self.headless {
+            cmd.arg("--headless");
+        }
+        match self.init {
+            InitMode::Default => (),
+            InitMode::NoConfig => {
+                cmd.args(&["-u", "NONE"]);
+            }
+            InitMode::Clean => {
+                cmd.arg("--clean");
+            }
+        }
+        cmd.args(&self.args);
+
+        if let Some(tmp_dir) = tmp_dir {
+            for &(var, name) in &[
+                ("XDG_CONFIG_HOME", "config"),
+                ("XDG_DATA_HOME", "data"),
+                ("XDG_STATE_HOME", "state"),
+            ] {
+                let path = tmp_dir.join(name);
+                fs::create_dir(&path)?;
+                cmd.env(var, path);
+            }
+        }
+        for (key, val) in &self.envs {
+            cmd.env(key, val);
+        }
+
+        if let Some(ref dir) = self.current_dir {
+            cmd.current_dir(dir);
+        }
+
+        Ok(cmd)
+    }
+}
+
+impl Default for EmbedOptions {
+    fn default() -> Self {
+        EmbedOptions::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command() {
+        let cmd = EmbedOptions::new()
+            .set_program("nvim")
+            .set_headless(true)
+            .set_no_config(true)
+            .arg("-n")
+            .command(None)
+            .unwrap();
+
+        assert_eq!(
+            r#""nvim" "--embed" "--headless" "-u" "NONE" "-n""#,
+            format!("{:?}", cmd)
+        );
+    }
+
+    #[test]
+    fn test_isolated_command() {
+        let tmp_dir = TempDir::new("neovim-lib.test").unwrap();
+        EmbedOptions::new()
+            .set_isolated(true)
+            .command(Some(tmp_dir.path()))
+            .unwrap();
+
+        assert!(tmp_dir.path().join("config").is_dir());
+        assert!(tmp_dir.path().join("data").is_dir());
+        assert!(tmp_dir.path().join("state").is_dir());
+    }
+
+    #[test]
+    fn test_no_embed() {
+        let err = EmbedOptions::new().set_embed(false).spawn().err().unwrap();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_handshake_exit() {
+        let err = EmbedOptions::new()
+            .set_program("false")
+            .set_startup_timeout(Duration::new(5, 0))
+            .spawn()
+            .err()
+            .unwrap();
+        assert_eq!(ErrorKind::BrokenPipe, err.kind());
+        assert!(err.to_string().contains("exit status: 1"), "{}", err);
+    }
+}
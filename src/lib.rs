@@ -31,7 +31,12 @@ extern crate rmp;
 extern crate rmpv;
 #[macro_use]
 extern crate log;
+extern crate tempdir;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 
+#[cfg(unix)]
+extern crate libc;
 #[cfg(unix)]
 extern crate unix_socket;
 
@@ -39,15 +44,50 @@ mod rpc;
 #[macro_use]
 pub mod session;
 pub mod async;
+pub mod bytes;
+pub mod embed;
+pub mod mock;
 pub mod neovim;
 pub mod neovim_api;
 pub mod neovim_api_async;
+pub mod position;
+pub mod reconnect;
+pub mod replay;
+pub mod server;
+pub mod stream;
 
 pub use async::AsyncCall;
-pub use neovim::{CallError, Neovim, UiAttachOptions, UiOption};
+pub use bytes::NeovimBytesApi;
+pub use embed::EmbedOptions;
+pub use mock::{MockCall, MockNeovim};
+pub use neovim::{
+    AsyncCallback, CallBackend, CallError, ExtTypes, Neovim, UiAttachOptions, UiOption,
+};
 pub use neovim_api::NeovimApi;
 pub use neovim_api_async::NeovimApiAsync;
-pub use session::Session;
+pub use position::{BufferLines, ColumnUnit, LineSource, LspPosition, Position, Range};
+pub use reconnect::{ConnectionState, ReconnectOptions, ReconnectingSession};
+pub use replay::{Divergence, Player, Replay};
+pub use server::Listener;
+pub use session::{Session, StderrMode};
+pub use stream::{Chunk, ChunkOptions, LineChunks};
 
 pub use rmpv::{Integer, Utf8String, Value};
-pub use rpc::handler::{Handler, RequestHandler};
+pub use rpc::handler::{Handler, RequestHandler, Responder};
+pub use rpc::channel::{ChannelOptions, NotifyReceiver, OverflowPolicy};
+pub use rpc::decoder::{DecodeLimits, ProtocolError};
+pub use rpc::args::{CallArgs, EncodeArg};
+pub use rpc::CancelHandle;
+pub use rpc::model::{FromVal, IntoVal, RpcMessage, TryFromVal};
+// request encoding, that client uses, exported for benchmarks
+#[doc(hidden)]
+pub use rpc::model::{encode as encode_message, encode_request};
+#[cfg(feature = "tracing")]
+pub use rpc::observer::TracingObserver;
+pub use rpc::observer::{
+    CancelEvent, Metrics, MethodStats, Observer, RequestEvent, ResponseEvent,
+};
+pub use rpc::pool::{NotifyOrder, PoolOptions};
+pub use rpc::record::{Direction, Record, Recorder};
+pub use rpc::router::{FromArgs, Router};
+pub use rpc::writer::WriterOptions;
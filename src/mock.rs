@@ -0,0 +1,317 @@
+//! In-process neovim stand-in for plugin unit tests
+//!
+//! ```
+//! use neovim_lib::{MockNeovim, Neovim, NeovimApi, Value};
+//!
+//! let (mock, mut session) = MockNeovim::new();
+//! mock.respond("nvim_get_current_line", Value::from("hello"))
+//!     .handle("nvim_set_current_line", |_| Ok(Value::Nil));
+//! session.start_event_loop();
+//! let mut nvim = Neovim::new(session);
+//!
+//! let line = nvim.get_current_line().unwrap();
+//! nvim.set_current_line(&line.to_uppercase()).unwrap();
+//!
+//! assert_eq!(vec![vec![Value::from("HELLO")]], mock.calls_to("nvim_set_current_line"));
+//! mock.verify();
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::io::BufReader;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use rmpv::Value;
+
+use neovim::{map_generic_error, CallError};
+use rpc::model::{self, RpcMessage};
+use rpc::pipe::{self, PipeReader, PipeWriter};
+use session::Session;
+
+type MethodHandler = Box<dyn FnMut(Vec<Value>) -> Result<Value, Value> + Send>;
+
+/// Message sent by client to mock
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockCall {
+    pub method: String,
+    pub params: Vec<Value>,
+    /// `true` for notification, `false` for request
+    pub notification: bool,
+}
+
+#[derive(Default)]
+struct MockState {
+    handlers: HashMap<String, MethodHandler>,
+    /// Methods, whose handler was called at least once
+    used: HashSet<String>,
+    calls: Vec<MockCall>,
+    unexpected: Vec<String>,
+    pending: HashMap<u64, mpsc::Sender<Result<Value, CallError>>>,
+    msgid_counter: u64,
+}
+
+/// Fake neovim, that answers requests with registered handlers
+///
+/// Runs on its own thread and talks to `Session` over in-memory pipes,
+/// so plugin code is tested through unchanged `Neovim`/`NeovimApi`.
+/// Request without registered handler is answered with error and reported by `verify`,
+/// as well as handler, that was never called.
+pub struct MockNeovim {
+    state: Arc<Mutex<MockState>>,
+    writer: Arc<Mutex<Option<PipeWriter>>>,
+    timeout: Duration,
+}
+
+impl MockNeovim {
+    /// Create mock and session connected to it, event loop is not started
+    pub fn new() -> (MockNeovim, Session) {
+        let (to_client, client_reader) = pipe::pipe();
+        let (client_writer, from_client) = pipe::pipe();
+
+        let mock = MockNeovim {
+            state: Arc::new(Mutex::new(MockState::default())),
+            writer: Arc::new(Mutex::new(Some(to_client))),
+            timeout: Duration::new(5, 0),
+        };
+
+        let state = mock.state.clone();
+        let writer = mock.writer.clone();
+        thread::Builder::new()
+            .name("nvim-mock".to_owned())
+            .spawn(move || serve(&state, &writer, from_client))
+            .expect("Can't spawn mock thread");
+
+        let mut session = Session::from_io(client_reader, client_writer);
+        let writer = mock.writer.clone();
+        session.set_shutdown_hook(move || {
+            writer.lock().unwrap().take();
+        });
+
+        (mock, session)
+    }
+
+    /// Time to wait client response in `request`, 5 seconds by default
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Answer every `method` request with `f`
+    ///
+    /// Called on mock thread, so `f` must not use the mock itself.
+    pub fn handle<F>(&self, method: &str, f: F) -> &Self
+    where
+        F: FnMut(Vec<Value>) -> Result<Value, Value> + Send + 'static,
+    {
+        self.state
+            .lock()
+            .unwrap()
+            .handlers
+            .insert(method.to_owned(), Box::new(f));
+        self
+    }
+
+    /// Answer every `method` request with `result`
+    pub fn respond(&self, method: &str, result: Value) -> &Self {
+        self.handle(method, move |_| Ok(result.clone()))
+    }
+
+    /// Answer every `method` request with neovim exception
+    pub fn respond_err(&self, method: &str, message: &str) -> &Self {
+        let err = Value::from(vec![Value::from(0), Value::from(message)]);
+        self.handle(method, move |_| Err(err.clone()))
+    }
+
+    /// Send notification to client
+    pub fn notify(&self, method: &str, params: Vec<Value>) {
+        self.write(RpcMessage::RpcNotification {
+            method: method.to_owned(),
+            params,
+        });
+    }
+
+    /// Send request to client and wait for response
+    pub fn request(&self, method: &str, params: Vec<Value>) -> Result<Value, CallError> {
+        let (sender, receiver) = mpsc::channel();
+        let msgid = {
+            let mut state = self.state.lock().unwrap();
+            let msgid = state.msgid_counter;
+            state.msgid_counter += 1;
+            state.pending.insert(msgid, sender);
+            msgid
+        };
+
+        self.write(RpcMessage::RpcRequest {
+            msgid,
+            method: method.to_owned(),
+            params,
+        });
+
+        match receiver.recv_timeout(self.timeout) {
+            Ok(res) => res,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                self.state.lock().unwrap().pending.remove(&msgid);
+                Err(CallError::GenericError(format!(
+                    "Wait timeout ({})",
+                    method
+                )))
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(CallError::Disconnected(
+                "Client closed connection".to_owned(),
+            )),
+        }
+    }
+
+    /// All requests and notifications received so far, in order of arrival
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Params of every `method` request or notification
+    pub fn calls_to(&self, method: &str) -> Vec<Vec<Value>> {
+        self.state
+            .lock()
+            .unwrap()
+            .calls
+            .iter()
+            .filter(|call| call.method == method)
+            .map(|call| call.params.clone())
+            .collect()
+    }
+
+    /// Forget received calls
+    pub fn clear_calls(&self) {
+        self.state.lock().unwrap().calls.clear();
+    }
+
+    /// Panics if client made requests without registered handler or never called registered one
+    pub fn verify(&self) {
+        let state = self.state.lock().unwrap();
+        if !state.unexpected.is_empty() {
+            panic!("Unexpected calls: {}", state.unexpected.join(", "));
+        }
+
+        let mut unused: Vec<&str> = state
+            .handlers
+            .keys()
+            .filter(|method| !state.used.contains(*method))
+            .map(|method| method.as_str())
+            .collect();
+        if !unused.is_empty() {
+            unused.sort();
+            panic!("Expected calls not made: {}", unused.join(", "));
+        }
+    }
+
+    /// Close connection, like neovim exits
+    pub fn close(&self) {
+        self.writer.lock().unwrap().take();
+    }
+
+    fn write(&self, msg: RpcMessage) {
+        if let Some(ref mut writer) = *self.writer.lock().unwrap() {
+            if let Err(e) = model::encode(writer, msg) {
+                debug!("Mock can't send message: {}", e);
+            }
+        }
+    }
+}
+
+impl Drop for MockNeovim {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn serve(
+    state: &Arc<Mutex<MockState>>,
+    writer: &Arc<Mutex<Option<PipeWriter>>>,
+    from_client: PipeReader,
+) {
+    let mut from_client = BufReader::new(from_client);
+
+    loop {
+        let msg = match model::decode(&mut from_client) {
+            Ok(msg) => msg,
+            Err(e) => {
+                debug!("Mock stopped: {}", e);
+                break;
+            }
+        };
+
+        match msg {
+            RpcMessage::RpcRequest {
+                msgid,
+                method,
+                params,
+            } => {
+                let res = state.lock().unwrap().call(method, params, false);
+                let response = match res {
+                    Ok(result) => RpcMessage::RpcResponse {
+                        msgid,
+                        error: Value::Nil,
+                        result,
+                    },
+                    Err(error) => RpcMessage::RpcResponse {
+                        msgid,
+                        error,
+                        result: Value::Nil,
+                    },
+                };
+                if let Some(ref mut writer) = *writer.lock().unwrap() {
+                    model::encode(writer, response).ok();
+                }
+            }
+            RpcMessage::RpcNotification { method, params } => {
+                state.lock().unwrap().call(method, params, true).ok();
+            }
+            RpcMessage::RpcResponse {
+                msgid,
+                error,
+                result,
+            } => {
+                let sender = state.lock().unwrap().pending.remove(&msgid);
+                match sender {
+                    Some(sender) if error != Value::Nil => {
+                        sender.send(Err(map_generic_error(error))).ok();
+                    }
+                    Some(sender) => {
+                        sender.send(Ok(result)).ok();
+                    }
+                    None => warn!("Mock got response to unknown request {}", msgid),
+                }
+            }
+        }
+    }
+
+    // pending requests fail as disconnected
+    state.lock().unwrap().pending.clear();
+}
+
+impl MockState {
+    fn call(
+        &mut self,
+        method: String,
+        params: Vec<Value>,
+        notification: bool,
+    ) -> Result<Value, Value> {
+        self.calls.push(MockCall {
+            method: method.clone(),
+            params: params.clone(),
+            notification,
+        });
+
+        match self.handlers.get_mut(&method) {
+            Some(handler) => {
+                self.used.insert(method);
+                handler(params)
+            }
+            None if notification => Ok(Value::Nil),
+            None => {
+                let err = format!("Unexpected call {}", method);
+                self.unexpected.push(method);
+                Err(Value::from(vec![Value::from(0), Value::from(err)]))
+            }
+        }
+    }
+}
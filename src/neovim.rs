@@ -2,13 +2,65 @@ use neovim_api::NeovimApi;
 use rmpv::Value;
 use rpc::*;
 use session::Session;
+use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
+use std::mem;
+use std::time::Duration;
 
 pub struct Neovim {
     pub session: Session,
 }
 
+/// Callback of async call, gets raw result value
+pub type AsyncCallback = Box<dyn FnMut(Result<Value, CallError>) + Send + 'static>;
+
+/// Transport, that generated `NeovimApi`, `NeovimApiAsync` and ext type methods call
+///
+/// Implemented by `Neovim` and `Session`. Custom implementation, for example
+/// dry-run or caching wrapper, gets the whole api with
+/// `impl NeovimApi for MyBackend {}`.
+pub trait CallBackend {
+    /// Sync call with already converted arguments
+    fn call(&mut self, method: &str, args: Vec<Value>) -> Result<Value, CallError>;
+
+    /// Sync call with borrowed arguments
+    ///
+    /// Used by generated sync api. Default converts arguments and uses `call`.
+    fn call_args(&mut self, method: &str, args: &CallArgs) -> Result<Value, CallError> {
+        self.call(method, args.to_values())
+    }
+
+    /// Async call, result is passed to `cb`
+    ///
+    /// Use `CancelHandle::detached` if call can't be cancelled.
+    fn call_async(
+        &mut self,
+        method: &str,
+        args: Vec<Value>,
+        cb: Option<AsyncCallback>,
+    ) -> CancelHandle;
+}
+
+impl CallBackend for Neovim {
+    fn call(&mut self, method: &str, args: Vec<Value>) -> Result<Value, CallError> {
+        self.session.call(method, args)
+    }
+
+    fn call_args(&mut self, method: &str, args: &CallArgs) -> Result<Value, CallError> {
+        self.session.call_args(method, args)
+    }
+
+    fn call_async(
+        &mut self,
+        method: &str,
+        args: Vec<Value>,
+        cb: Option<AsyncCallback>,
+    ) -> CancelHandle {
+        CallBackend::call_async(&mut self.session, method, args, cb)
+    }
+}
+
 pub enum UiOption {
     RGB(bool),
     ExtPopupmenu(bool),
@@ -101,10 +153,71 @@ impl UiAttachOptions {
     }
 }
 
+/// Ext type ids, used by neovim to encode `Buffer`, `Window` and `Tabpage`
+///
+/// Ids are advertised by server in `get_api_info` response,
+/// default value contains ids used by current neovim versions.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ExtTypes {
+    pub buffer: i8,
+    pub window: i8,
+    pub tabpage: i8,
+}
+
+impl Default for ExtTypes {
+    fn default() -> Self {
+        ExtTypes {
+            buffer: 0,
+            window: 1,
+            tabpage: 2,
+        }
+    }
+}
+
+impl ExtTypes {
+    /// Parse ext type ids from `get_api_info` response
+    pub fn from_api_info(api_info: &[Value]) -> Option<ExtTypes> {
+        let types = api_info
+            .get(1)?
+            .as_map()?
+            .iter()
+            .find(|(k, _)| k.as_str() == Some("types"))?
+            .1
+            .as_map()?;
+
+        let find_id = |name: &str| {
+            types
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(name))
+                .and_then(|(_, info)| info.as_map())
+                .and_then(|info| {
+                    info.iter()
+                        .find(|(k, _)| k.as_str() == Some("id"))
+                        .and_then(|(_, id)| id.as_i64())
+                })
+                .and_then(|id| match i8::try_from(id) {
+                    Ok(id) => Some(id),
+                    Err(_) => {
+                        warn!("Ext type id {} of {} is out of range", id, name);
+                        None
+                    }
+                })
+        };
+
+        Some(ExtTypes {
+            buffer: find_id("Buffer")?,
+            window: find_id("Window")?,
+            tabpage: find_id("Tabpage")?,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum CallError {
     GenericError(String),
     NeovimError(i64, String),
+    /// Connection to neovim is lost or session is closed
+    Disconnected(String),
 }
 
 impl fmt::Display for CallError {
@@ -112,6 +225,7 @@ impl fmt::Display for CallError {
         match *self {
             CallError::GenericError(ref s) => write!(f, "Unknown error type: {}", s),
             CallError::NeovimError(id, ref s) => write!(f, "{} - {}", id, s),
+            CallError::Disconnected(ref s) => write!(f, "Disconnected: {}", s),
         }
     }
 }
@@ -121,6 +235,7 @@ impl Error for CallError {
         match *self {
             CallError::GenericError(ref s) => s,
             CallError::NeovimError(_, ref s) => s,
+            CallError::Disconnected(ref s) => s,
         }
     }
 }
@@ -151,6 +266,23 @@ pub fn map_result<T: FromVal<Value>>(val: Value) -> T {
     T::from_val(val)
 }
 
+#[doc(hidden)]
+pub fn try_map_result<T: TryFromVal<Value>>(val: Value) -> Result<T, CallError> {
+    T::try_from_val(val).map_err(CallError::GenericError)
+}
+
+/// Puts session timeout back, when dropped
+struct RestoreTimeout<'a> {
+    neovim: &'a mut Neovim,
+    timeout: Option<Duration>,
+}
+
+impl<'a> Drop for RestoreTimeout<'a> {
+    fn drop(&mut self) {
+        self.neovim.session.timeout = self.timeout;
+    }
+}
+
 impl Neovim {
     pub fn new(session: Session) -> Neovim {
         Neovim { session }
@@ -169,10 +301,25 @@ impl Neovim {
             .call(
                 "nvim_ui_attach",
                 call_args!(width, height, opts.to_value_map()),
-            ).map_err(map_generic_error)
+            )
             .map(|_| ())
     }
 
+    /// Run calls made by `f` with `timeout` instead of session timeout,
+    /// `None` waits without limit
+    pub fn with_timeout<F, T>(&mut self, timeout: Option<Duration>, f: F) -> T
+    where
+        F: FnOnce(&mut Neovim) -> T,
+    {
+        let session_timeout = mem::replace(&mut self.session.timeout, timeout);
+        // restored on drop, even if `f` panics
+        let guard = RestoreTimeout {
+            neovim: self,
+            timeout: session_timeout,
+        };
+        f(guard.neovim)
+    }
+
     /// Send a quit command to Nvim.
     /// The quit command is 'qa!' which will make Nvim quit without
     /// saving anything.
@@ -180,6 +327,15 @@ impl Neovim {
         self.command("qa!")
     }
 
+    /// Request ext type ids from server
+    ///
+    /// Can be used to construct `Buffer`, `Window` and `Tabpage` from handle number
+    pub fn ext_types(&mut self) -> Result<ExtTypes, CallError> {
+        let api_info = self.get_api_info()?;
+        ExtTypes::from_api_info(&api_info)
+            .ok_or_else(|| CallError::GenericError("Can't find ext types in api info".to_owned()))
+    }
+
     /// Same as `ui_set_option` but use `UiOption` as argument to check type at compile time
     pub fn set_option(&mut self, option: UiOption) -> Result<(), CallError> {
         let name_value = option.to_name_value();
@@ -190,6 +346,63 @@ impl Neovim {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use mock::MockNeovim;
+    use neovim_api::Buffer;
+    use neovim_api_async::NeovimApiAsync;
+    use std::collections::HashSet;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct DryRun {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CallBackend for DryRun {
+        fn call(&mut self, method: &str, _args: Vec<Value>) -> Result<Value, CallError> {
+            self.calls.lock().unwrap().push(method.to_owned());
+            Ok(Value::from(7))
+        }
+
+        fn call_async(
+            &mut self,
+            method: &str,
+            args: Vec<Value>,
+            cb: Option<AsyncCallback>,
+        ) -> CancelHandle {
+            let res = self.call(method, args);
+            if let Some(mut cb) = cb {
+                cb(res);
+            }
+            CancelHandle::detached()
+        }
+    }
+
+    impl NeovimApi for DryRun {}
+    impl NeovimApiAsync for DryRun {}
+
+    #[test]
+    fn test_custom_backend() {
+        let mut backend = DryRun::default();
+        let buf = Buffer::from_handle(&ExtTypes::default(), 1);
+
+        assert_eq!(Ok(7), backend.strwidth("text"));
+        assert_eq!(Ok(7), buf.line_count(&mut backend));
+
+        let result = Arc::new(Mutex::new(None));
+        let cb_result = result.clone();
+        let handle = backend
+            .input_async("x")
+            .cb(move |res| *cb_result.lock().unwrap() = Some(res))
+            .call();
+        assert!(!handle.cancel());
+        assert_eq!(Some(Ok(7)), *result.lock().unwrap());
+
+        assert_eq!(
+            vec!["nvim_strwidth", "nvim_buf_line_count", "nvim_input"],
+            *backend.calls.lock().unwrap()
+        );
+    }
 
     #[test]
     fn test_ui_options() {
@@ -207,4 +420,88 @@ mod tests {
             value_map
         );
     }
+
+    #[test]
+    fn test_ext_types() {
+        let type_info = |id: i64| Value::Map(vec![("id".into(), id.into())]);
+        let api_info = vec![
+            Value::from(1),
+            Value::Map(vec![(
+                "types".into(),
+                Value::Map(vec![
+                    ("Buffer".into(), type_info(3)),
+                    ("Window".into(), type_info(4)),
+                    ("Tabpage".into(), type_info(5)),
+                ]),
+            )]),
+        ];
+
+        assert_eq!(
+            Some(ExtTypes {
+                buffer: 3,
+                window: 4,
+                tabpage: 5,
+            }),
+            ExtTypes::from_api_info(&api_info)
+        );
+        assert_eq!(None, ExtTypes::from_api_info(&[]));
+
+        let api_info = vec![
+            Value::from(1),
+            Value::Map(vec![(
+                "types".into(),
+                Value::Map(vec![
+                    ("Buffer".into(), type_info(3)),
+                    ("Window".into(), type_info(300)),
+                    ("Tabpage".into(), type_info(5)),
+                ]),
+            )]),
+        ];
+        assert_eq!(None, ExtTypes::from_api_info(&api_info));
+    }
+
+    #[test]
+    fn test_buffer_handle() {
+        let ext_types = ExtTypes::default();
+        let buf = Buffer::from_handle(&ext_types, 3);
+        assert_eq!(Some(3), buf.handle());
+        assert_eq!(buf, Buffer::new(Value::Ext(0, vec![0x03])));
+        assert!(Buffer::from_handle(&ext_types, 1) < buf);
+
+        let mut set = HashSet::new();
+        set.insert(buf.clone());
+        set.insert(Buffer::from_handle(&ext_types, 3));
+        assert_eq!(1, set.len());
+    }
+
+    #[test]
+    fn test_buffer_without_handle() {
+        let buf = Buffer::new(Value::from("not a handle"));
+        assert_eq!(None, buf.handle());
+        assert!(Buffer::try_new(Value::from("not a handle")).is_err());
+        assert!(Buffer::try_from_val(Value::Ext(0, vec![0xc1])).is_err());
+
+        assert_eq!(buf, Buffer::new(Value::from("not a handle")));
+        assert_ne!(buf, Buffer::new(Value::from(5)));
+        assert!(buf < Buffer::from_handle(&ExtTypes::default(), 0));
+
+        let mut set = HashSet::new();
+        set.insert(buf.clone());
+        set.insert(Buffer::new(Value::from("not a handle")));
+        set.insert(Buffer::new(Value::from(5)));
+        assert_eq!(2, set.len());
+    }
+
+    #[test]
+    fn test_with_timeout_restores_on_panic() {
+        let (_mock, session) = MockNeovim::new();
+        let mut nvim = Neovim::new(session);
+        let timeout = nvim.session.timeout;
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            nvim.with_timeout(None, |_| panic!("handler failed"))
+        }));
+        assert!(res.is_err());
+        assert_eq!(timeout, nvim.session.timeout);
+    }
 }
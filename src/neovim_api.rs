@@ -2,15 +2,37 @@
 
 use neovim::*;
 use rpc::*;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Buffer {
     code_data: Value,
+    handle: Option<i64>,
 }
 
 impl Buffer {
+    /// Wrap value, handle is `None` when value isn't ext handle
     pub fn new(code_data: Value) -> Buffer {
-        Buffer { code_data }
+        let handle = model::decode_ext_handle(&code_data).map(|(_, handle)| handle);
+        Buffer { code_data, handle }
+    }
+
+    /// Same as `new`, but fails when value isn't ext handle
+    pub fn try_new(code_data: Value) -> Result<Buffer, String> {
+        match model::decode_ext_handle(&code_data) {
+            Some((_, handle)) => Ok(Buffer {
+                code_data,
+                handle: Some(handle),
+            }),
+            None => Err(format!("Can't decode Buffer handle from {}", code_data)),
+        }
+    }
+
+    /// Create from handle number, ext type id is taken from `ext_types`
+    pub fn from_handle(ext_types: &ExtTypes, handle: i64) -> Buffer {
+        Buffer::new(model::encode_ext_handle(ext_types.buffer, handle))
     }
 
     /// Internal value, that represent type
@@ -18,243 +40,198 @@ impl Buffer {
         &self.code_data
     }
 
+    /// Numeric handle, decoded from internal value, `None` when value isn't ext handle
+    pub fn handle(&self) -> Option<i64> {
+        self.handle
+    }
+
     /// since: 1
-    pub fn line_count(&self, neovim: &mut Neovim) -> Result<i64, CallError> {
+    pub fn line_count<B: CallBackend>(&self, neovim: &mut B) -> Result<i64, CallError> {
         neovim
-            .session
-            .call("nvim_buf_line_count", call_args![self.code_data.clone()])
+            .call_args("nvim_buf_line_count", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 4
-    pub fn attach(
+    pub fn attach<B: CallBackend>(
         &self,
-        neovim: &mut Neovim,
+        neovim: &mut B,
         send_buffer: bool,
         opts: Vec<(Value, Value)>,
     ) -> Result<bool, CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_buf_attach",
-                call_args![self.code_data.clone(), send_buffer, opts],
+                &encode_args![self.code_data, send_buffer, opts],
             )
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 4
-    pub fn detach(&self, neovim: &mut Neovim) -> Result<bool, CallError> {
+    pub fn detach<B: CallBackend>(&self, neovim: &mut B) -> Result<bool, CallError> {
         neovim
-            .session
-            .call("nvim_buf_detach", call_args![self.code_data.clone()])
+            .call_args("nvim_buf_detach", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_lines(
+    ///
+    /// Fails on invalid utf-8, see `bytes` module for binary safe variant
+    pub fn get_lines<B: CallBackend>(
         &self,
-        neovim: &mut Neovim,
+        neovim: &mut B,
         start: i64,
         end: i64,
         strict_indexing: bool,
     ) -> Result<Vec<String>, CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_buf_get_lines",
-                call_args![self.code_data.clone(), start, end, strict_indexing],
+                &encode_args![self.code_data, start, end, strict_indexing],
             )
-            .map(map_result)
-            .map_err(map_generic_error)
+            .and_then(try_map_result)
     }
     /// since: 1
-    pub fn set_lines(
+    pub fn set_lines<B: CallBackend>(
         &self,
-        neovim: &mut Neovim,
+        neovim: &mut B,
         start: i64,
         end: i64,
         strict_indexing: bool,
         replacement: Vec<String>,
     ) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_buf_set_lines",
-                call_args![
-                    self.code_data.clone(),
-                    start,
-                    end,
-                    strict_indexing,
-                    replacement
-                ],
+                &encode_args![self.code_data, start, end, strict_indexing, replacement],
             )
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 5
-    pub fn get_offset(&self, neovim: &mut Neovim, index: i64) -> Result<i64, CallError> {
+    pub fn get_offset<B: CallBackend>(&self, neovim: &mut B, index: i64) -> Result<i64, CallError> {
         neovim
-            .session
-            .call(
-                "nvim_buf_get_offset",
-                call_args![self.code_data.clone(), index],
-            )
+            .call_args("nvim_buf_get_offset", &encode_args![self.code_data, index])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_var(&self, neovim: &mut Neovim, name: &str) -> Result<Value, CallError> {
+    pub fn get_var<B: CallBackend>(&self, neovim: &mut B, name: &str) -> Result<Value, CallError> {
         neovim
-            .session
-            .call("nvim_buf_get_var", call_args![self.code_data.clone(), name])
+            .call_args("nvim_buf_get_var", &encode_args![self.code_data, name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 2
-    pub fn get_changedtick(&self, neovim: &mut Neovim) -> Result<i64, CallError> {
+    pub fn get_changedtick<B: CallBackend>(&self, neovim: &mut B) -> Result<i64, CallError> {
         neovim
-            .session
-            .call(
-                "nvim_buf_get_changedtick",
-                call_args![self.code_data.clone()],
-            )
+            .call_args("nvim_buf_get_changedtick", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 3
-    pub fn get_keymap(
+    pub fn get_keymap<B: CallBackend>(
         &self,
-        neovim: &mut Neovim,
+        neovim: &mut B,
         mode: &str,
     ) -> Result<Vec<Vec<(Value, Value)>>, CallError> {
         neovim
-            .session
-            .call(
-                "nvim_buf_get_keymap",
-                call_args![self.code_data.clone(), mode],
-            )
+            .call_args("nvim_buf_get_keymap", &encode_args![self.code_data, mode])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 4
-    pub fn get_commands(
+    pub fn get_commands<B: CallBackend>(
         &self,
-        neovim: &mut Neovim,
+        neovim: &mut B,
         opts: Vec<(Value, Value)>,
     ) -> Result<Vec<(Value, Value)>, CallError> {
         neovim
-            .session
-            .call(
-                "nvim_buf_get_commands",
-                call_args![self.code_data.clone(), opts],
-            )
+            .call_args("nvim_buf_get_commands", &encode_args![self.code_data, opts])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn set_var(&self, neovim: &mut Neovim, name: &str, value: Value) -> Result<(), CallError> {
+    pub fn set_var<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        name: &str,
+        value: Value,
+    ) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_buf_set_var",
-                call_args![self.code_data.clone(), name, value],
+                &encode_args![self.code_data, name, value],
             )
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn del_var(&self, neovim: &mut Neovim, name: &str) -> Result<(), CallError> {
+    pub fn del_var<B: CallBackend>(&self, neovim: &mut B, name: &str) -> Result<(), CallError> {
         neovim
-            .session
-            .call("nvim_buf_del_var", call_args![self.code_data.clone(), name])
+            .call_args("nvim_buf_del_var", &encode_args![self.code_data, name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_option(&self, neovim: &mut Neovim, name: &str) -> Result<Value, CallError> {
+    pub fn get_option<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        name: &str,
+    ) -> Result<Value, CallError> {
         neovim
-            .session
-            .call(
-                "nvim_buf_get_option",
-                call_args![self.code_data.clone(), name],
-            )
+            .call_args("nvim_buf_get_option", &encode_args![self.code_data, name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn set_option(
+    pub fn set_option<B: CallBackend>(
         &self,
-        neovim: &mut Neovim,
+        neovim: &mut B,
         name: &str,
         value: Value,
     ) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_buf_set_option",
-                call_args![self.code_data.clone(), name, value],
+                &encode_args![self.code_data, name, value],
             )
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_number(&self, neovim: &mut Neovim) -> Result<i64, CallError> {
+    pub fn get_number<B: CallBackend>(&self, neovim: &mut B) -> Result<i64, CallError> {
         neovim
-            .session
-            .call("nvim_buf_get_number", call_args![self.code_data.clone()])
+            .call_args("nvim_buf_get_number", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_name(&self, neovim: &mut Neovim) -> Result<String, CallError> {
+    ///
+    /// Fails on invalid utf-8, see `bytes` module for binary safe variant
+    pub fn get_name<B: CallBackend>(&self, neovim: &mut B) -> Result<String, CallError> {
         neovim
-            .session
-            .call("nvim_buf_get_name", call_args![self.code_data.clone()])
-            .map(map_result)
-            .map_err(map_generic_error)
+            .call_args("nvim_buf_get_name", &encode_args![self.code_data])
+            .and_then(try_map_result)
     }
     /// since: 1
-    pub fn set_name(&self, neovim: &mut Neovim, name: &str) -> Result<(), CallError> {
+    pub fn set_name<B: CallBackend>(&self, neovim: &mut B, name: &str) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
-                "nvim_buf_set_name",
-                call_args![self.code_data.clone(), name],
-            )
+            .call_args("nvim_buf_set_name", &encode_args![self.code_data, name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 5
-    pub fn is_loaded(&self, neovim: &mut Neovim) -> Result<bool, CallError> {
+    pub fn is_loaded<B: CallBackend>(&self, neovim: &mut B) -> Result<bool, CallError> {
         neovim
-            .session
-            .call("nvim_buf_is_loaded", call_args![self.code_data.clone()])
+            .call_args("nvim_buf_is_loaded", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn is_valid(&self, neovim: &mut Neovim) -> Result<bool, CallError> {
+    pub fn is_valid<B: CallBackend>(&self, neovim: &mut B) -> Result<bool, CallError> {
         neovim
-            .session
-            .call("nvim_buf_is_valid", call_args![self.code_data.clone()])
+            .call_args("nvim_buf_is_valid", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_mark(&self, neovim: &mut Neovim, name: &str) -> Result<(i64, i64), CallError> {
+    pub fn get_mark<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        name: &str,
+    ) -> Result<(i64, i64), CallError> {
         neovim
-            .session
-            .call(
-                "nvim_buf_get_mark",
-                call_args![self.code_data.clone(), name],
-            )
+            .call_args("nvim_buf_get_mark", &encode_args![self.code_data, name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn add_highlight(
+    pub fn add_highlight<B: CallBackend>(
         &self,
-        neovim: &mut Neovim,
+        neovim: &mut B,
         ns_id: i64,
         hl_group: &str,
         line: i64,
@@ -262,83 +239,87 @@ impl Buffer {
         col_end: i64,
     ) -> Result<i64, CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_buf_add_highlight",
-                call_args![
-                    self.code_data.clone(),
-                    ns_id,
-                    hl_group,
-                    line,
-                    col_start,
-                    col_end
-                ],
+                &encode_args![self.code_data, ns_id, hl_group, line, col_start, col_end],
             )
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 5
-    pub fn clear_namespace(
+    pub fn clear_namespace<B: CallBackend>(
         &self,
-        neovim: &mut Neovim,
+        neovim: &mut B,
         ns_id: i64,
         line_start: i64,
         line_end: i64,
     ) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_buf_clear_namespace",
-                call_args![self.code_data.clone(), ns_id, line_start, line_end],
+                &encode_args![self.code_data, ns_id, line_start, line_end],
             )
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn clear_highlight(
+    pub fn clear_highlight<B: CallBackend>(
         &self,
-        neovim: &mut Neovim,
+        neovim: &mut B,
         ns_id: i64,
         line_start: i64,
         line_end: i64,
     ) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_buf_clear_highlight",
-                call_args![self.code_data.clone(), ns_id, line_start, line_end],
+                &encode_args![self.code_data, ns_id, line_start, line_end],
             )
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 5
-    pub fn set_virtual_text(
+    pub fn set_virtual_text<B: CallBackend>(
         &self,
-        neovim: &mut Neovim,
+        neovim: &mut B,
         ns_id: i64,
         line: i64,
         chunks: Vec<Value>,
         opts: Vec<(Value, Value)>,
     ) -> Result<i64, CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_buf_set_virtual_text",
-                call_args![self.code_data.clone(), ns_id, line, chunks, opts],
+                &encode_args![self.code_data, ns_id, line, chunks, opts],
             )
             .map(map_result)
-            .map_err(map_generic_error)
     }
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Window {
     code_data: Value,
+    handle: Option<i64>,
 }
 
 impl Window {
+    /// Wrap value, handle is `None` when value isn't ext handle
     pub fn new(code_data: Value) -> Window {
-        Window { code_data }
+        let handle = model::decode_ext_handle(&code_data).map(|(_, handle)| handle);
+        Window { code_data, handle }
+    }
+
+    /// Same as `new`, but fails when value isn't ext handle
+    pub fn try_new(code_data: Value) -> Result<Window, String> {
+        match model::decode_ext_handle(&code_data) {
+            Some((_, handle)) => Ok(Window {
+                code_data,
+                handle: Some(handle),
+            }),
+            None => Err(format!("Can't decode Window handle from {}", code_data)),
+        }
+    }
+
+    /// Create from handle number, ext type id is taken from `ext_types`
+    pub fn from_handle(ext_types: &ExtTypes, handle: i64) -> Window {
+        Window::new(model::encode_ext_handle(ext_types.window, handle))
     }
 
     /// Internal value, that represent type
@@ -346,178 +327,170 @@ impl Window {
         &self.code_data
     }
 
+    /// Numeric handle, decoded from internal value, `None` when value isn't ext handle
+    pub fn handle(&self) -> Option<i64> {
+        self.handle
+    }
+
     /// since: 1
-    pub fn get_buf(&self, neovim: &mut Neovim) -> Result<Buffer, CallError> {
+    pub fn get_buf<B: CallBackend>(&self, neovim: &mut B) -> Result<Buffer, CallError> {
         neovim
-            .session
-            .call("nvim_win_get_buf", call_args![self.code_data.clone()])
+            .call_args("nvim_win_get_buf", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 5
-    pub fn set_buf(&self, neovim: &mut Neovim, buffer: &Buffer) -> Result<(), CallError> {
+    pub fn set_buf<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        buffer: &Buffer,
+    ) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
-                "nvim_win_set_buf",
-                call_args![self.code_data.clone(), buffer],
-            )
+            .call_args("nvim_win_set_buf", &encode_args![self.code_data, buffer])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_cursor(&self, neovim: &mut Neovim) -> Result<(i64, i64), CallError> {
+    pub fn get_cursor<B: CallBackend>(&self, neovim: &mut B) -> Result<(i64, i64), CallError> {
         neovim
-            .session
-            .call("nvim_win_get_cursor", call_args![self.code_data.clone()])
+            .call_args("nvim_win_get_cursor", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn set_cursor(&self, neovim: &mut Neovim, pos: (i64, i64)) -> Result<(), CallError> {
+    pub fn set_cursor<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        pos: (i64, i64),
+    ) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
-                "nvim_win_set_cursor",
-                call_args![self.code_data.clone(), pos],
-            )
+            .call_args("nvim_win_set_cursor", &encode_args![self.code_data, pos])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_height(&self, neovim: &mut Neovim) -> Result<i64, CallError> {
+    pub fn get_height<B: CallBackend>(&self, neovim: &mut B) -> Result<i64, CallError> {
         neovim
-            .session
-            .call("nvim_win_get_height", call_args![self.code_data.clone()])
+            .call_args("nvim_win_get_height", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn set_height(&self, neovim: &mut Neovim, height: i64) -> Result<(), CallError> {
+    pub fn set_height<B: CallBackend>(&self, neovim: &mut B, height: i64) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
-                "nvim_win_set_height",
-                call_args![self.code_data.clone(), height],
-            )
+            .call_args("nvim_win_set_height", &encode_args![self.code_data, height])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_width(&self, neovim: &mut Neovim) -> Result<i64, CallError> {
+    pub fn get_width<B: CallBackend>(&self, neovim: &mut B) -> Result<i64, CallError> {
         neovim
-            .session
-            .call("nvim_win_get_width", call_args![self.code_data.clone()])
+            .call_args("nvim_win_get_width", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn set_width(&self, neovim: &mut Neovim, width: i64) -> Result<(), CallError> {
+    pub fn set_width<B: CallBackend>(&self, neovim: &mut B, width: i64) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
-                "nvim_win_set_width",
-                call_args![self.code_data.clone(), width],
-            )
+            .call_args("nvim_win_set_width", &encode_args![self.code_data, width])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_var(&self, neovim: &mut Neovim, name: &str) -> Result<Value, CallError> {
+    pub fn get_var<B: CallBackend>(&self, neovim: &mut B, name: &str) -> Result<Value, CallError> {
         neovim
-            .session
-            .call("nvim_win_get_var", call_args![self.code_data.clone(), name])
+            .call_args("nvim_win_get_var", &encode_args![self.code_data, name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn set_var(&self, neovim: &mut Neovim, name: &str, value: Value) -> Result<(), CallError> {
+    pub fn set_var<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        name: &str,
+        value: Value,
+    ) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_win_set_var",
-                call_args![self.code_data.clone(), name, value],
+                &encode_args![self.code_data, name, value],
             )
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn del_var(&self, neovim: &mut Neovim, name: &str) -> Result<(), CallError> {
+    pub fn del_var<B: CallBackend>(&self, neovim: &mut B, name: &str) -> Result<(), CallError> {
         neovim
-            .session
-            .call("nvim_win_del_var", call_args![self.code_data.clone(), name])
+            .call_args("nvim_win_del_var", &encode_args![self.code_data, name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_option(&self, neovim: &mut Neovim, name: &str) -> Result<Value, CallError> {
+    pub fn get_option<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        name: &str,
+    ) -> Result<Value, CallError> {
         neovim
-            .session
-            .call(
-                "nvim_win_get_option",
-                call_args![self.code_data.clone(), name],
-            )
+            .call_args("nvim_win_get_option", &encode_args![self.code_data, name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn set_option(
+    pub fn set_option<B: CallBackend>(
         &self,
-        neovim: &mut Neovim,
+        neovim: &mut B,
         name: &str,
         value: Value,
     ) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_win_set_option",
-                call_args![self.code_data.clone(), name, value],
+                &encode_args![self.code_data, name, value],
             )
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_position(&self, neovim: &mut Neovim) -> Result<(i64, i64), CallError> {
+    pub fn get_position<B: CallBackend>(&self, neovim: &mut B) -> Result<(i64, i64), CallError> {
         neovim
-            .session
-            .call("nvim_win_get_position", call_args![self.code_data.clone()])
+            .call_args("nvim_win_get_position", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_tabpage(&self, neovim: &mut Neovim) -> Result<Tabpage, CallError> {
+    pub fn get_tabpage<B: CallBackend>(&self, neovim: &mut B) -> Result<Tabpage, CallError> {
         neovim
-            .session
-            .call("nvim_win_get_tabpage", call_args![self.code_data.clone()])
+            .call_args("nvim_win_get_tabpage", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_number(&self, neovim: &mut Neovim) -> Result<i64, CallError> {
+    pub fn get_number<B: CallBackend>(&self, neovim: &mut B) -> Result<i64, CallError> {
         neovim
-            .session
-            .call("nvim_win_get_number", call_args![self.code_data.clone()])
+            .call_args("nvim_win_get_number", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn is_valid(&self, neovim: &mut Neovim) -> Result<bool, CallError> {
+    pub fn is_valid<B: CallBackend>(&self, neovim: &mut B) -> Result<bool, CallError> {
         neovim
-            .session
-            .call("nvim_win_is_valid", call_args![self.code_data.clone()])
+            .call_args("nvim_win_is_valid", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Tabpage {
     code_data: Value,
+    handle: Option<i64>,
 }
 
 impl Tabpage {
+    /// Wrap value, handle is `None` when value isn't ext handle
     pub fn new(code_data: Value) -> Tabpage {
-        Tabpage { code_data }
+        let handle = model::decode_ext_handle(&code_data).map(|(_, handle)| handle);
+        Tabpage { code_data, handle }
+    }
+
+    /// Same as `new`, but fails when value isn't ext handle
+    pub fn try_new(code_data: Value) -> Result<Tabpage, String> {
+        match model::decode_ext_handle(&code_data) {
+            Some((_, handle)) => Ok(Tabpage {
+                code_data,
+                handle: Some(handle),
+            }),
+            None => Err(format!("Can't decode Tabpage handle from {}", code_data)),
+        }
+    }
+
+    /// Create from handle number, ext type id is taken from `ext_types`
+    pub fn from_handle(ext_types: &ExtTypes, handle: i64) -> Tabpage {
+        Tabpage::new(model::encode_ext_handle(ext_types.tabpage, handle))
     }
 
     /// Internal value, that represent type
@@ -525,73 +498,60 @@ impl Tabpage {
         &self.code_data
     }
 
+    /// Numeric handle, decoded from internal value, `None` when value isn't ext handle
+    pub fn handle(&self) -> Option<i64> {
+        self.handle
+    }
+
     /// since: 1
-    pub fn list_wins(&self, neovim: &mut Neovim) -> Result<Vec<Window>, CallError> {
+    pub fn list_wins<B: CallBackend>(&self, neovim: &mut B) -> Result<Vec<Window>, CallError> {
         neovim
-            .session
-            .call("nvim_tabpage_list_wins", call_args![self.code_data.clone()])
+            .call_args("nvim_tabpage_list_wins", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_var(&self, neovim: &mut Neovim, name: &str) -> Result<Value, CallError> {
+    pub fn get_var<B: CallBackend>(&self, neovim: &mut B, name: &str) -> Result<Value, CallError> {
         neovim
-            .session
-            .call(
-                "nvim_tabpage_get_var",
-                call_args![self.code_data.clone(), name],
-            )
+            .call_args("nvim_tabpage_get_var", &encode_args![self.code_data, name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn set_var(&self, neovim: &mut Neovim, name: &str, value: Value) -> Result<(), CallError> {
+    pub fn set_var<B: CallBackend>(
+        &self,
+        neovim: &mut B,
+        name: &str,
+        value: Value,
+    ) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
+            .call_args(
                 "nvim_tabpage_set_var",
-                call_args![self.code_data.clone(), name, value],
+                &encode_args![self.code_data, name, value],
             )
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn del_var(&self, neovim: &mut Neovim, name: &str) -> Result<(), CallError> {
+    pub fn del_var<B: CallBackend>(&self, neovim: &mut B, name: &str) -> Result<(), CallError> {
         neovim
-            .session
-            .call(
-                "nvim_tabpage_del_var",
-                call_args![self.code_data.clone(), name],
-            )
+            .call_args("nvim_tabpage_del_var", &encode_args![self.code_data, name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_win(&self, neovim: &mut Neovim) -> Result<Window, CallError> {
+    pub fn get_win<B: CallBackend>(&self, neovim: &mut B) -> Result<Window, CallError> {
         neovim
-            .session
-            .call("nvim_tabpage_get_win", call_args![self.code_data.clone()])
+            .call_args("nvim_tabpage_get_win", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn get_number(&self, neovim: &mut Neovim) -> Result<i64, CallError> {
+    pub fn get_number<B: CallBackend>(&self, neovim: &mut B) -> Result<i64, CallError> {
         neovim
-            .session
-            .call(
-                "nvim_tabpage_get_number",
-                call_args![self.code_data.clone()],
-            )
+            .call_args("nvim_tabpage_get_number", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
     /// since: 1
-    pub fn is_valid(&self, neovim: &mut Neovim) -> Result<bool, CallError> {
+    pub fn is_valid<B: CallBackend>(&self, neovim: &mut B) -> Result<bool, CallError> {
         neovim
-            .session
-            .call("nvim_tabpage_is_valid", call_args![self.code_data.clone()])
+            .call_args("nvim_tabpage_is_valid", &encode_args![self.code_data])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 }
 
@@ -601,230 +561,209 @@ impl FromVal<Value> for Buffer {
     }
 }
 
+impl TryFromVal<Value> for Buffer {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        Buffer::try_new(val)
+    }
+}
+
 impl<'a> IntoVal<Value> for &'a Buffer {
     fn into_val(self) -> Value {
         self.code_data.clone()
     }
 }
+
+impl EncodeArg for Buffer {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        self.code_data.encode_arg(writer)
+    }
+}
+
+impl PartialEq for Buffer {
+    fn eq(&self, other: &Buffer) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Buffer {}
+
+impl Hash for Buffer {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.handle.hash(state)
+    }
+}
+
+impl PartialOrd for Buffer {
+    fn partial_cmp(&self, other: &Buffer) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Buffer {
+    fn cmp(&self, other: &Buffer) -> Ordering {
+        match (self.handle, other.handle) {
+            (None, None) => model::cmp_undecoded(&self.code_data, &other.code_data),
+            (handle, other_handle) => handle.cmp(&other_handle),
+        }
+    }
+}
 impl FromVal<Value> for Window {
     fn from_val(val: Value) -> Self {
         Window::new(val)
     }
 }
 
+impl TryFromVal<Value> for Window {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        Window::try_new(val)
+    }
+}
+
 impl<'a> IntoVal<Value> for &'a Window {
     fn into_val(self) -> Value {
         self.code_data.clone()
     }
 }
+
+impl EncodeArg for Window {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        self.code_data.encode_arg(writer)
+    }
+}
+
+impl PartialEq for Window {
+    fn eq(&self, other: &Window) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Window {}
+
+impl Hash for Window {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.handle.hash(state)
+    }
+}
+
+impl PartialOrd for Window {
+    fn partial_cmp(&self, other: &Window) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Window {
+    fn cmp(&self, other: &Window) -> Ordering {
+        match (self.handle, other.handle) {
+            (None, None) => model::cmp_undecoded(&self.code_data, &other.code_data),
+            (handle, other_handle) => handle.cmp(&other_handle),
+        }
+    }
+}
 impl FromVal<Value> for Tabpage {
     fn from_val(val: Value) -> Self {
         Tabpage::new(val)
     }
 }
 
+impl TryFromVal<Value> for Tabpage {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        Tabpage::try_new(val)
+    }
+}
+
 impl<'a> IntoVal<Value> for &'a Tabpage {
     fn into_val(self) -> Value {
         self.code_data.clone()
     }
 }
 
-pub trait NeovimApi {
-    /// since: 1
-    fn ui_detach(&mut self) -> Result<(), CallError>;
-    /// since: 1
-    fn ui_try_resize(&mut self, width: i64, height: i64) -> Result<(), CallError>;
-    /// since: 1
-    fn ui_set_option(&mut self, name: &str, value: Value) -> Result<(), CallError>;
-    /// since: 1
-    fn command(&mut self, command: &str) -> Result<(), CallError>;
-    /// since: 3
-    fn get_hl_by_name(&mut self, name: &str, rgb: bool) -> Result<Vec<(Value, Value)>, CallError>;
-    /// since: 3
-    fn get_hl_by_id(&mut self, hl_id: i64, rgb: bool) -> Result<Vec<(Value, Value)>, CallError>;
-    /// since: 1
-    fn feedkeys(&mut self, keys: &str, mode: &str, escape_csi: bool) -> Result<(), CallError>;
-    /// since: 1
-    fn input(&mut self, keys: &str) -> Result<i64, CallError>;
-    /// since: 1
-    fn replace_termcodes(
-        &mut self,
-        str: &str,
-        from_part: bool,
-        do_lt: bool,
-        special: bool,
-    ) -> Result<String, CallError>;
-    /// since: 1
-    fn command_output(&mut self, command: &str) -> Result<String, CallError>;
-    /// since: 1
-    fn eval(&mut self, expr: &str) -> Result<Value, CallError>;
-    /// since: 3
-    fn execute_lua(&mut self, code: &str, args: Vec<Value>) -> Result<Value, CallError>;
-    /// since: 1
-    fn call_function(&mut self, fname: &str, args: Vec<Value>) -> Result<Value, CallError>;
-    /// since: 4
-    fn call_dict_function(
-        &mut self,
-        dict: Value,
-        fname: &str,
-        args: Vec<Value>,
-    ) -> Result<Value, CallError>;
-    /// since: 1
-    fn strwidth(&mut self, text: &str) -> Result<i64, CallError>;
-    /// since: 1
-    fn list_runtime_paths(&mut self) -> Result<Vec<String>, CallError>;
-    /// since: 1
-    fn set_current_dir(&mut self, dir: &str) -> Result<(), CallError>;
-    /// since: 1
-    fn get_current_line(&mut self) -> Result<String, CallError>;
-    /// since: 1
-    fn set_current_line(&mut self, line: &str) -> Result<(), CallError>;
-    /// since: 1
-    fn del_current_line(&mut self) -> Result<(), CallError>;
-    /// since: 1
-    fn get_var(&mut self, name: &str) -> Result<Value, CallError>;
-    /// since: 1
-    fn set_var(&mut self, name: &str, value: Value) -> Result<(), CallError>;
-    /// since: 1
-    fn del_var(&mut self, name: &str) -> Result<(), CallError>;
-    /// since: 1
-    fn get_vvar(&mut self, name: &str) -> Result<Value, CallError>;
-    /// since: 1
-    fn get_option(&mut self, name: &str) -> Result<Value, CallError>;
-    /// since: 1
-    fn set_option(&mut self, name: &str, value: Value) -> Result<(), CallError>;
-    /// since: 1
-    fn out_write(&mut self, str: &str) -> Result<(), CallError>;
-    /// since: 1
-    fn err_write(&mut self, str: &str) -> Result<(), CallError>;
-    /// since: 1
-    fn err_writeln(&mut self, str: &str) -> Result<(), CallError>;
-    /// since: 1
-    fn list_bufs(&mut self) -> Result<Vec<Buffer>, CallError>;
-    /// since: 1
-    fn get_current_buf(&mut self) -> Result<Buffer, CallError>;
-    /// since: 1
-    fn set_current_buf(&mut self, buffer: &Buffer) -> Result<(), CallError>;
-    /// since: 1
-    fn list_wins(&mut self) -> Result<Vec<Window>, CallError>;
-    /// since: 1
-    fn get_current_win(&mut self) -> Result<Window, CallError>;
-    /// since: 1
-    fn set_current_win(&mut self, window: &Window) -> Result<(), CallError>;
-    /// since: 1
-    fn list_tabpages(&mut self) -> Result<Vec<Tabpage>, CallError>;
-    /// since: 1
-    fn get_current_tabpage(&mut self) -> Result<Tabpage, CallError>;
-    /// since: 1
-    fn set_current_tabpage(&mut self, tabpage: &Tabpage) -> Result<(), CallError>;
-    /// since: 5
-    fn create_namespace(&mut self, name: &str) -> Result<i64, CallError>;
-    /// since: 5
-    fn get_namespaces(&mut self) -> Result<Vec<(Value, Value)>, CallError>;
-    /// since: 1
-    fn subscribe(&mut self, event: &str) -> Result<(), CallError>;
-    /// since: 1
-    fn unsubscribe(&mut self, event: &str) -> Result<(), CallError>;
-    /// since: 1
-    fn get_color_by_name(&mut self, name: &str) -> Result<i64, CallError>;
-    /// since: 1
-    fn get_color_map(&mut self) -> Result<Vec<(Value, Value)>, CallError>;
-    /// since: 2
-    fn get_mode(&mut self) -> Result<Vec<(Value, Value)>, CallError>;
-    /// since: 3
-    fn get_keymap(&mut self, mode: &str) -> Result<Vec<Vec<(Value, Value)>>, CallError>;
-    /// since: 4
-    fn get_commands(&mut self, opts: Vec<(Value, Value)>)
-        -> Result<Vec<(Value, Value)>, CallError>;
-    /// since: 1
-    fn get_api_info(&mut self) -> Result<Vec<Value>, CallError>;
-    /// since: 4
-    fn set_client_info(
-        &mut self,
-        name: &str,
-        version: Vec<(Value, Value)>,
-        typ: &str,
-        methods: Vec<(Value, Value)>,
-        attributes: Vec<(Value, Value)>,
-    ) -> Result<(), CallError>;
-    /// since: 4
-    fn get_chan_info(&mut self, chan: i64) -> Result<Vec<(Value, Value)>, CallError>;
-    /// since: 4
-    fn list_chans(&mut self) -> Result<Vec<Value>, CallError>;
-    /// since: 1
-    fn call_atomic(&mut self, calls: Vec<Value>) -> Result<Vec<Value>, CallError>;
-    /// since: 4
-    fn parse_expression(
-        &mut self,
-        expr: &str,
-        flags: &str,
-        highlight: bool,
-    ) -> Result<Vec<(Value, Value)>, CallError>;
-    /// since: 4
-    fn list_uis(&mut self) -> Result<Vec<Value>, CallError>;
-    /// since: 4
-    fn get_proc_children(&mut self, pid: i64) -> Result<Vec<Value>, CallError>;
-    /// since: 4
-    fn get_proc(&mut self, pid: i64) -> Result<Value, CallError>;
+impl EncodeArg for Tabpage {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        self.code_data.encode_arg(writer)
+    }
+}
+
+impl PartialEq for Tabpage {
+    fn eq(&self, other: &Tabpage) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Tabpage {}
+
+impl Hash for Tabpage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.handle.hash(state)
+    }
+}
+
+impl PartialOrd for Tabpage {
+    fn partial_cmp(&self, other: &Tabpage) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
-impl NeovimApi for Neovim {
+impl Ord for Tabpage {
+    fn cmp(&self, other: &Tabpage) -> Ordering {
+        match (self.handle, other.handle) {
+            (None, None) => model::cmp_undecoded(&self.code_data, &other.code_data),
+            (handle, other_handle) => handle.cmp(&other_handle),
+        }
+    }
+}
+
+pub trait NeovimApi: CallBackend {
+    /// since: 1
     fn ui_detach(&mut self) -> Result<(), CallError> {
-        self.session
-            .call("nvim_ui_detach", call_args![])
+        self.call_args("nvim_ui_detach", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn ui_try_resize(&mut self, width: i64, height: i64) -> Result<(), CallError> {
-        self.session
-            .call("nvim_ui_try_resize", call_args![width, height])
+        self.call_args("nvim_ui_try_resize", &encode_args![width, height])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn ui_set_option(&mut self, name: &str, value: Value) -> Result<(), CallError> {
-        self.session
-            .call("nvim_ui_set_option", call_args![name, value])
+        self.call_args("nvim_ui_set_option", &encode_args![name, value])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn command(&mut self, command: &str) -> Result<(), CallError> {
-        self.session
-            .call("nvim_command", call_args![command])
+        self.call_args("nvim_command", &encode_args![command])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 3
     fn get_hl_by_name(&mut self, name: &str, rgb: bool) -> Result<Vec<(Value, Value)>, CallError> {
-        self.session
-            .call("nvim_get_hl_by_name", call_args![name, rgb])
+        self.call_args("nvim_get_hl_by_name", &encode_args![name, rgb])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 3
     fn get_hl_by_id(&mut self, hl_id: i64, rgb: bool) -> Result<Vec<(Value, Value)>, CallError> {
-        self.session
-            .call("nvim_get_hl_by_id", call_args![hl_id, rgb])
+        self.call_args("nvim_get_hl_by_id", &encode_args![hl_id, rgb])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn feedkeys(&mut self, keys: &str, mode: &str, escape_csi: bool) -> Result<(), CallError> {
-        self.session
-            .call("nvim_feedkeys", call_args![keys, mode, escape_csi])
+        self.call_args("nvim_feedkeys", &encode_args![keys, mode, escape_csi])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn input(&mut self, keys: &str) -> Result<i64, CallError> {
-        self.session
-            .call("nvim_input", call_args![keys])
+        self.call_args("nvim_input", &encode_args![keys])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
+    ///
+    /// Fails on invalid utf-8, see `bytes` module for binary safe variant
     fn replace_termcodes(
         &mut self,
         str: &str,
@@ -832,296 +771,262 @@ impl NeovimApi for Neovim {
         do_lt: bool,
         special: bool,
     ) -> Result<String, CallError> {
-        self.session
-            .call(
-                "nvim_replace_termcodes",
-                call_args![str, from_part, do_lt, special],
-            )
-            .map(map_result)
-            .map_err(map_generic_error)
+        self.call_args(
+            "nvim_replace_termcodes",
+            &encode_args![str, from_part, do_lt, special],
+        )
+        .and_then(try_map_result)
     }
 
+    /// since: 1
+    ///
+    /// Fails on invalid utf-8, see `bytes` module for binary safe variant
     fn command_output(&mut self, command: &str) -> Result<String, CallError> {
-        self.session
-            .call("nvim_command_output", call_args![command])
-            .map(map_result)
-            .map_err(map_generic_error)
+        self.call_args("nvim_command_output", &encode_args![command])
+            .and_then(try_map_result)
     }
 
+    /// since: 1
     fn eval(&mut self, expr: &str) -> Result<Value, CallError> {
-        self.session
-            .call("nvim_eval", call_args![expr])
+        self.call_args("nvim_eval", &encode_args![expr])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 3
     fn execute_lua(&mut self, code: &str, args: Vec<Value>) -> Result<Value, CallError> {
-        self.session
-            .call("nvim_execute_lua", call_args![code, args])
+        self.call_args("nvim_execute_lua", &encode_args![code, args])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn call_function(&mut self, fname: &str, args: Vec<Value>) -> Result<Value, CallError> {
-        self.session
-            .call("nvim_call_function", call_args![fname, args])
+        self.call_args("nvim_call_function", &encode_args![fname, args])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 4
     fn call_dict_function(
         &mut self,
         dict: Value,
         fname: &str,
         args: Vec<Value>,
     ) -> Result<Value, CallError> {
-        self.session
-            .call("nvim_call_dict_function", call_args![dict, fname, args])
+        self.call_args("nvim_call_dict_function", &encode_args![dict, fname, args])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn strwidth(&mut self, text: &str) -> Result<i64, CallError> {
-        self.session
-            .call("nvim_strwidth", call_args![text])
+        self.call_args("nvim_strwidth", &encode_args![text])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
+    ///
+    /// Fails on invalid utf-8, see `bytes` module for binary safe variant
     fn list_runtime_paths(&mut self) -> Result<Vec<String>, CallError> {
-        self.session
-            .call("nvim_list_runtime_paths", call_args![])
-            .map(map_result)
-            .map_err(map_generic_error)
+        self.call_args("nvim_list_runtime_paths", &encode_args![])
+            .and_then(try_map_result)
     }
 
+    /// since: 1
     fn set_current_dir(&mut self, dir: &str) -> Result<(), CallError> {
-        self.session
-            .call("nvim_set_current_dir", call_args![dir])
+        self.call_args("nvim_set_current_dir", &encode_args![dir])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
+    ///
+    /// Fails on invalid utf-8, see `bytes` module for binary safe variant
     fn get_current_line(&mut self) -> Result<String, CallError> {
-        self.session
-            .call("nvim_get_current_line", call_args![])
-            .map(map_result)
-            .map_err(map_generic_error)
+        self.call_args("nvim_get_current_line", &encode_args![])
+            .and_then(try_map_result)
     }
 
+    /// since: 1
     fn set_current_line(&mut self, line: &str) -> Result<(), CallError> {
-        self.session
-            .call("nvim_set_current_line", call_args![line])
+        self.call_args("nvim_set_current_line", &encode_args![line])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn del_current_line(&mut self) -> Result<(), CallError> {
-        self.session
-            .call("nvim_del_current_line", call_args![])
+        self.call_args("nvim_del_current_line", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn get_var(&mut self, name: &str) -> Result<Value, CallError> {
-        self.session
-            .call("nvim_get_var", call_args![name])
+        self.call_args("nvim_get_var", &encode_args![name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn set_var(&mut self, name: &str, value: Value) -> Result<(), CallError> {
-        self.session
-            .call("nvim_set_var", call_args![name, value])
+        self.call_args("nvim_set_var", &encode_args![name, value])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn del_var(&mut self, name: &str) -> Result<(), CallError> {
-        self.session
-            .call("nvim_del_var", call_args![name])
+        self.call_args("nvim_del_var", &encode_args![name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn get_vvar(&mut self, name: &str) -> Result<Value, CallError> {
-        self.session
-            .call("nvim_get_vvar", call_args![name])
+        self.call_args("nvim_get_vvar", &encode_args![name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn get_option(&mut self, name: &str) -> Result<Value, CallError> {
-        self.session
-            .call("nvim_get_option", call_args![name])
+        self.call_args("nvim_get_option", &encode_args![name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn set_option(&mut self, name: &str, value: Value) -> Result<(), CallError> {
-        self.session
-            .call("nvim_set_option", call_args![name, value])
+        self.call_args("nvim_set_option", &encode_args![name, value])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn out_write(&mut self, str: &str) -> Result<(), CallError> {
-        self.session
-            .call("nvim_out_write", call_args![str])
+        self.call_args("nvim_out_write", &encode_args![str])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn err_write(&mut self, str: &str) -> Result<(), CallError> {
-        self.session
-            .call("nvim_err_write", call_args![str])
+        self.call_args("nvim_err_write", &encode_args![str])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn err_writeln(&mut self, str: &str) -> Result<(), CallError> {
-        self.session
-            .call("nvim_err_writeln", call_args![str])
+        self.call_args("nvim_err_writeln", &encode_args![str])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn list_bufs(&mut self) -> Result<Vec<Buffer>, CallError> {
-        self.session
-            .call("nvim_list_bufs", call_args![])
+        self.call_args("nvim_list_bufs", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn get_current_buf(&mut self) -> Result<Buffer, CallError> {
-        self.session
-            .call("nvim_get_current_buf", call_args![])
+        self.call_args("nvim_get_current_buf", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn set_current_buf(&mut self, buffer: &Buffer) -> Result<(), CallError> {
-        self.session
-            .call("nvim_set_current_buf", call_args![buffer])
+        self.call_args("nvim_set_current_buf", &encode_args![buffer])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn list_wins(&mut self) -> Result<Vec<Window>, CallError> {
-        self.session
-            .call("nvim_list_wins", call_args![])
+        self.call_args("nvim_list_wins", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn get_current_win(&mut self) -> Result<Window, CallError> {
-        self.session
-            .call("nvim_get_current_win", call_args![])
+        self.call_args("nvim_get_current_win", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn set_current_win(&mut self, window: &Window) -> Result<(), CallError> {
-        self.session
-            .call("nvim_set_current_win", call_args![window])
+        self.call_args("nvim_set_current_win", &encode_args![window])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn list_tabpages(&mut self) -> Result<Vec<Tabpage>, CallError> {
-        self.session
-            .call("nvim_list_tabpages", call_args![])
+        self.call_args("nvim_list_tabpages", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn get_current_tabpage(&mut self) -> Result<Tabpage, CallError> {
-        self.session
-            .call("nvim_get_current_tabpage", call_args![])
+        self.call_args("nvim_get_current_tabpage", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn set_current_tabpage(&mut self, tabpage: &Tabpage) -> Result<(), CallError> {
-        self.session
-            .call("nvim_set_current_tabpage", call_args![tabpage])
+        self.call_args("nvim_set_current_tabpage", &encode_args![tabpage])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 5
     fn create_namespace(&mut self, name: &str) -> Result<i64, CallError> {
-        self.session
-            .call("nvim_create_namespace", call_args![name])
+        self.call_args("nvim_create_namespace", &encode_args![name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 5
     fn get_namespaces(&mut self) -> Result<Vec<(Value, Value)>, CallError> {
-        self.session
-            .call("nvim_get_namespaces", call_args![])
+        self.call_args("nvim_get_namespaces", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn subscribe(&mut self, event: &str) -> Result<(), CallError> {
-        self.session
-            .call("nvim_subscribe", call_args![event])
+        self.call_args("nvim_subscribe", &encode_args![event])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn unsubscribe(&mut self, event: &str) -> Result<(), CallError> {
-        self.session
-            .call("nvim_unsubscribe", call_args![event])
+        self.call_args("nvim_unsubscribe", &encode_args![event])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn get_color_by_name(&mut self, name: &str) -> Result<i64, CallError> {
-        self.session
-            .call("nvim_get_color_by_name", call_args![name])
+        self.call_args("nvim_get_color_by_name", &encode_args![name])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn get_color_map(&mut self) -> Result<Vec<(Value, Value)>, CallError> {
-        self.session
-            .call("nvim_get_color_map", call_args![])
+        self.call_args("nvim_get_color_map", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 2
     fn get_mode(&mut self) -> Result<Vec<(Value, Value)>, CallError> {
-        self.session
-            .call("nvim_get_mode", call_args![])
+        self.call_args("nvim_get_mode", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 3
     fn get_keymap(&mut self, mode: &str) -> Result<Vec<Vec<(Value, Value)>>, CallError> {
-        self.session
-            .call("nvim_get_keymap", call_args![mode])
+        self.call_args("nvim_get_keymap", &encode_args![mode])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 4
     fn get_commands(
         &mut self,
         opts: Vec<(Value, Value)>,
     ) -> Result<Vec<(Value, Value)>, CallError> {
-        self.session
-            .call("nvim_get_commands", call_args![opts])
+        self.call_args("nvim_get_commands", &encode_args![opts])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn get_api_info(&mut self) -> Result<Vec<Value>, CallError> {
-        self.session
-            .call("nvim_get_api_info", call_args![])
+        self.call_args("nvim_get_api_info", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 4
     fn set_client_info(
         &mut self,
         name: &str,
@@ -1130,66 +1035,62 @@ impl NeovimApi for Neovim {
         methods: Vec<(Value, Value)>,
         attributes: Vec<(Value, Value)>,
     ) -> Result<(), CallError> {
-        self.session
-            .call(
-                "nvim_set_client_info",
-                call_args![name, version, typ, methods, attributes],
-            )
-            .map(map_result)
-            .map_err(map_generic_error)
+        self.call_args(
+            "nvim_set_client_info",
+            &encode_args![name, version, typ, methods, attributes],
+        )
+        .map(map_result)
     }
 
+    /// since: 4
     fn get_chan_info(&mut self, chan: i64) -> Result<Vec<(Value, Value)>, CallError> {
-        self.session
-            .call("nvim_get_chan_info", call_args![chan])
+        self.call_args("nvim_get_chan_info", &encode_args![chan])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 4
     fn list_chans(&mut self) -> Result<Vec<Value>, CallError> {
-        self.session
-            .call("nvim_list_chans", call_args![])
+        self.call_args("nvim_list_chans", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 1
     fn call_atomic(&mut self, calls: Vec<Value>) -> Result<Vec<Value>, CallError> {
-        self.session
-            .call("nvim_call_atomic", call_args![calls])
+        self.call_args("nvim_call_atomic", &encode_args![calls])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 4
     fn parse_expression(
         &mut self,
         expr: &str,
         flags: &str,
         highlight: bool,
     ) -> Result<Vec<(Value, Value)>, CallError> {
-        self.session
-            .call("nvim_parse_expression", call_args![expr, flags, highlight])
-            .map(map_result)
-            .map_err(map_generic_error)
+        self.call_args(
+            "nvim_parse_expression",
+            &encode_args![expr, flags, highlight],
+        )
+        .map(map_result)
     }
 
+    /// since: 4
     fn list_uis(&mut self) -> Result<Vec<Value>, CallError> {
-        self.session
-            .call("nvim_list_uis", call_args![])
+        self.call_args("nvim_list_uis", &encode_args![])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 4
     fn get_proc_children(&mut self, pid: i64) -> Result<Vec<Value>, CallError> {
-        self.session
-            .call("nvim_get_proc_children", call_args![pid])
+        self.call_args("nvim_get_proc_children", &encode_args![pid])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 
+    /// since: 4
     fn get_proc(&mut self, pid: i64) -> Result<Value, CallError> {
-        self.session
-            .call("nvim_get_proc", call_args![pid])
+        self.call_args("nvim_get_proc", &encode_args![pid])
             .map(map_result)
-            .map_err(map_generic_error)
     }
 }
+
+impl NeovimApi for Neovim {}
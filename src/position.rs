@@ -0,0 +1,437 @@
+//! Buffer positions and conversions between coordinate systems
+//!
+//! Neovim api mixes conventions: `Window::get_cursor` and `Buffer::get_mark` return
+//! 1-based line with 0-based byte column, `Buffer::add_highlight` and `Buffer::get_lines`
+//! take 0-based lines, vimscript `line()`/`col()` are 1-based, and LSP counts
+//! columns in UTF-16 code units. `Position` keeps 0-based line and 0-based byte column,
+//! other systems are converted explicitly.
+//!
+//! Columns in characters or UTF-16 units depend on line content, that is taken
+//! from `LineSource`: local mirror of lines or `BufferLines`, that fetches them.
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::str::{self, Chars};
+
+use neovim::{CallBackend, CallError};
+use neovim_api::Buffer;
+
+/// Unit of column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnUnit {
+    /// Bytes, as used by neovim api
+    Byte,
+    /// Unicode scalar values
+    Char,
+    /// UTF-16 code units, as used by LSP
+    Utf16,
+}
+
+/// Part of line, that is one character or one invalid byte
+struct Unit {
+    len: usize,
+    utf16: usize,
+}
+
+fn units(line: &[u8]) -> Units<'_> {
+    Units {
+        valid: "".chars(),
+        invalid: 0,
+        rest: line,
+    }
+}
+
+/// Splits line into `Unit`s, invalid sequence gives one unit per byte
+struct Units<'a> {
+    valid: Chars<'a>,
+    invalid: usize,
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for Units<'a> {
+    type Item = Unit;
+
+    fn next(&mut self) -> Option<Unit> {
+        loop {
+            if let Some(c) = self.valid.next() {
+                return Some(Unit {
+                    len: c.len_utf8(),
+                    utf16: c.len_utf16(),
+                });
+            }
+            if self.invalid > 0 {
+                self.invalid -= 1;
+                return Some(Unit { len: 1, utf16: 1 });
+            }
+            if self.rest.is_empty() {
+                return None;
+            }
+
+            let rest = self.rest;
+            let (valid, invalid) = match str::from_utf8(rest) {
+                Ok(_) => (rest.len(), 0),
+                Err(e) => (
+                    e.valid_up_to(),
+                    e.error_len().unwrap_or(rest.len() - e.valid_up_to()),
+                ),
+            };
+            self.valid = str::from_utf8(&rest[..valid]).unwrap().chars();
+            self.invalid = invalid;
+            self.rest = &rest[valid + invalid..];
+        }
+    }
+}
+
+fn unit_len(unit: &Unit, column: ColumnUnit) -> usize {
+    match column {
+        ColumnUnit::Byte => unit.len,
+        ColumnUnit::Char => 1,
+        ColumnUnit::Utf16 => unit.utf16,
+    }
+}
+
+/// Convert column of `line` between units
+///
+/// Column inside of character is moved to its start, column past line end
+/// is clamped to line end. Invalid utf-8 byte counts as one character.
+pub fn convert_column(line: &[u8], col: usize, from: ColumnUnit, to: ColumnUnit) -> usize {
+    let mut seen_from = 0;
+    let mut seen_to = 0;
+    for unit in units(line) {
+        let next = seen_from + unit_len(&unit, from);
+        if next > col {
+            break;
+        }
+        seen_from = next;
+        seen_to += unit_len(&unit, to);
+    }
+    seen_to
+}
+
+/// LSP position, 0-based line and UTF-16 column
+pub type LspPosition = (i64, i64);
+
+/// Content of buffer lines, used to convert columns
+pub trait LineSource {
+    /// Line by 0-based index
+    fn line(&self, line: i64) -> Result<Cow<'_, [u8]>, CallError>;
+}
+
+fn out_of_range(line: i64) -> CallError {
+    CallError::GenericError(format!("Line {} is out of range", line))
+}
+
+fn mirror_line<T: AsRef<[u8]>>(lines: &[T], line: i64) -> Result<Cow<'_, [u8]>, CallError> {
+    if line < 0 {
+        return Err(out_of_range(line));
+    }
+    lines
+        .get(line as usize)
+        .map(|line| Cow::Borrowed(line.as_ref()))
+        .ok_or_else(|| out_of_range(line))
+}
+
+impl LineSource for [String] {
+    fn line(&self, line: i64) -> Result<Cow<'_, [u8]>, CallError> {
+        mirror_line(self, line)
+    }
+}
+
+impl LineSource for Vec<String> {
+    fn line(&self, line: i64) -> Result<Cow<'_, [u8]>, CallError> {
+        mirror_line(self, line)
+    }
+}
+
+impl LineSource for [Vec<u8>] {
+    fn line(&self, line: i64) -> Result<Cow<'_, [u8]>, CallError> {
+        mirror_line(self, line)
+    }
+}
+
+impl LineSource for Vec<Vec<u8>> {
+    fn line(&self, line: i64) -> Result<Cow<'_, [u8]>, CallError> {
+        mirror_line(self, line)
+    }
+}
+
+/// Lines, that are fetched from buffer on every lookup
+pub struct BufferLines<'a, B: CallBackend + 'a> {
+    buffer: &'a Buffer,
+    neovim: RefCell<&'a mut B>,
+}
+
+impl<'a, B: CallBackend + 'a> BufferLines<'a, B> {
+    pub fn new(buffer: &'a Buffer, neovim: &'a mut B) -> BufferLines<'a, B> {
+        BufferLines {
+            buffer,
+            neovim: RefCell::new(neovim),
+        }
+    }
+}
+
+impl<'a, B: CallBackend + 'a> LineSource for BufferLines<'a, B> {
+    fn line(&self, line: i64) -> Result<Cow<'_, [u8]>, CallError> {
+        let mut neovim = self.neovim.borrow_mut();
+        let mut lines = self
+            .buffer
+            .get_lines_bytes(&mut **neovim, line, line + 1, true)?;
+        lines
+            .pop()
+            .map(Cow::Owned)
+            .ok_or_else(|| out_of_range(line))
+    }
+}
+
+/// Position in buffer, 0-based line and 0-based byte column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub line: i64,
+    pub col: i64,
+}
+
+impl Position {
+    pub fn new(line: i64, col: i64) -> Position {
+        Position { line, col }
+    }
+
+    /// From 1-based line and 0-based byte column,
+    /// as returned by `Window::get_cursor` and `Buffer::get_mark`
+    pub fn from_cursor(cursor: (i64, i64)) -> Position {
+        Position::new(cursor.0 - 1, cursor.1)
+    }
+
+    /// To 1-based line and 0-based byte column, as taken by `Window::set_cursor`
+    pub fn to_cursor(&self) -> (i64, i64) {
+        (self.line + 1, self.col)
+    }
+
+    /// From 1-based line and 1-based byte column, as returned by vimscript `line()` and `col()`
+    pub fn from_one_based(line: i64, col: i64) -> Position {
+        Position::new(line - 1, col - 1)
+    }
+
+    pub fn to_one_based(&self) -> (i64, i64) {
+        (self.line + 1, self.col + 1)
+    }
+
+    /// From 0-based line and column in given unit
+    pub fn from_column<S: LineSource + ?Sized>(
+        line: i64,
+        col: i64,
+        unit: ColumnUnit,
+        source: &S,
+    ) -> Result<Position, CallError> {
+        let col = match unit {
+            ColumnUnit::Byte => col,
+            unit => {
+                let content = source.line(line)?;
+                convert_column(&content, col.max(0) as usize, unit, ColumnUnit::Byte) as i64
+            }
+        };
+        Ok(Position::new(line, col))
+    }
+
+    /// Column in given unit
+    pub fn column<S: LineSource + ?Sized>(
+        &self,
+        unit: ColumnUnit,
+        source: &S,
+    ) -> Result<i64, CallError> {
+        match unit {
+            ColumnUnit::Byte => Ok(self.col),
+            unit => {
+                let content = source.line(self.line)?;
+                Ok(
+                    convert_column(&content, self.col.max(0) as usize, ColumnUnit::Byte, unit)
+                        as i64,
+                )
+            }
+        }
+    }
+
+    /// From LSP position, 0-based line and UTF-16 column
+    pub fn from_lsp<S: LineSource + ?Sized>(
+        line: i64,
+        character: i64,
+        source: &S,
+    ) -> Result<Position, CallError> {
+        Position::from_column(line, character, ColumnUnit::Utf16, source)
+    }
+
+    /// To LSP position, 0-based line and UTF-16 column
+    pub fn to_lsp<S: LineSource + ?Sized>(&self, source: &S) -> Result<LspPosition, CallError> {
+        Ok((self.line, self.column(ColumnUnit::Utf16, source)?))
+    }
+}
+
+/// Range in buffer, `end` is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    pub fn new(start: Position, end: Position) -> Range {
+        Range { start, end }
+    }
+
+    /// From LSP range, with UTF-16 columns
+    pub fn from_lsp<S: LineSource + ?Sized>(
+        start: LspPosition,
+        end: LspPosition,
+        source: &S,
+    ) -> Result<Range, CallError> {
+        Ok(Range::new(
+            Position::from_lsp(start.0, start.1, source)?,
+            Position::from_lsp(end.0, end.1, source)?,
+        ))
+    }
+
+    /// To LSP range, with UTF-16 columns
+    pub fn to_lsp<S: LineSource + ?Sized>(
+        &self,
+        source: &S,
+    ) -> Result<(LspPosition, LspPosition), CallError> {
+        Ok((self.start.to_lsp(source)?, self.end.to_lsp(source)?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    /// `(line, col_start, col_end)` for every line of range,
+    /// as taken by `Buffer::add_highlight`, `-1` means end of line
+    pub fn highlight_lines(&self) -> Vec<(i64, i64, i64)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        if self.start.line == self.end.line {
+            return vec![(self.start.line, self.start.col, self.end.col)];
+        }
+
+        let mut lines = vec![(self.start.line, self.start.col, -1)];
+        lines.extend((self.start.line + 1..self.end.line).map(|line| (line, 0, -1)));
+        if self.end.col > 0 {
+            lines.push((self.end.line, 0, self.end.col));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockNeovim;
+    use neovim::Neovim;
+    use rmpv::Value;
+    use rpc::model;
+
+    // "a" 1 byte, "é" 2 bytes, "😀" 4 bytes and 2 utf-16 units
+    const LINE: &str = "aé😀b";
+
+    #[test]
+    fn test_convert_column() {
+        let line = LINE.as_bytes();
+        let byte_cols = [0, 1, 3, 7, 8];
+        let char_cols = [0, 1, 2, 3, 4];
+        let utf16_cols = [0, 1, 2, 4, 5];
+
+        for i in 0..byte_cols.len() {
+            use self::ColumnUnit::*;
+            assert_eq!(char_cols[i], convert_column(line, byte_cols[i], Byte, Char));
+            assert_eq!(
+                utf16_cols[i],
+                convert_column(line, byte_cols[i], Byte, Utf16)
+            );
+            assert_eq!(byte_cols[i], convert_column(line, char_cols[i], Char, Byte));
+            assert_eq!(
+                byte_cols[i],
+                convert_column(line, utf16_cols[i], Utf16, Byte)
+            );
+            assert_eq!(
+                utf16_cols[i],
+                convert_column(line, char_cols[i], Char, Utf16)
+            );
+        }
+
+        // inside of character and past the end
+        assert_eq!(
+            1,
+            convert_column(line, 2, ColumnUnit::Byte, ColumnUnit::Char)
+        );
+        assert_eq!(
+            3,
+            convert_column(line, 3, ColumnUnit::Utf16, ColumnUnit::Byte)
+        );
+        assert_eq!(
+            8,
+            convert_column(line, 100, ColumnUnit::Char, ColumnUnit::Byte)
+        );
+
+        // invalid byte is one character
+        assert_eq!(
+            2,
+            convert_column(b"\xffa", 2, ColumnUnit::Byte, ColumnUnit::Char)
+        );
+        // broken sequence inside and truncated one at the end
+        let line = b"\xc3a\xe2\x82b\xf0\x9f";
+        assert_eq!(
+            7,
+            convert_column(line, 7, ColumnUnit::Byte, ColumnUnit::Utf16)
+        );
+        assert_eq!(
+            5,
+            convert_column(line, 5, ColumnUnit::Char, ColumnUnit::Byte)
+        );
+    }
+
+    #[test]
+    fn test_conventions() {
+        let pos = Position::from_cursor((1, 3));
+        assert_eq!(Position::new(0, 3), pos);
+        assert_eq!((1, 3), pos.to_cursor());
+        assert_eq!((1, 4), pos.to_one_based());
+        assert_eq!(pos, Position::from_one_based(1, 4));
+
+        let lines = vec!["x".to_owned(), LINE.to_owned()];
+        let pos = Position::from_lsp(1, 4, &lines).unwrap();
+        assert_eq!(Position::new(1, 7), pos);
+        assert_eq!((1, 4), pos.to_lsp(&lines).unwrap());
+        assert_eq!(Ok(3), pos.column(ColumnUnit::Char, &lines));
+        assert!(Position::new(2, 0).to_lsp(&lines).is_err());
+    }
+
+    #[test]
+    fn test_highlight_lines() {
+        let range = Range::new(Position::new(1, 2), Position::new(3, 4));
+        assert_eq!(
+            vec![(1, 2, -1), (2, 0, -1), (3, 0, 4)],
+            range.highlight_lines()
+        );
+
+        let range = Range::new(Position::new(1, 2), Position::new(2, 0));
+        assert_eq!(vec![(1, 2, -1)], range.highlight_lines());
+
+        let range = Range::new(Position::new(1, 2), Position::new(1, 5));
+        assert_eq!(vec![(1, 2, 5)], range.highlight_lines());
+        assert!(Range::new(Position::new(1, 2), Position::new(1, 2)).is_empty());
+    }
+
+    #[test]
+    fn test_buffer_lines() {
+        let (mock, mut session) = MockNeovim::new();
+        mock.handle("nvim_buf_get_lines", |args| {
+            assert_eq!(Value::from(1), args[1]);
+            Ok(Value::from(vec![Value::from(LINE)]))
+        });
+        session.start_event_loop();
+        let mut nvim = Neovim::new(session);
+        let buf = Buffer::new(model::encode_ext_handle(0, 1));
+
+        let lines = BufferLines::new(&buf, &mut nvim);
+        let range = Range::from_lsp((1, 2), (1, 4), &lines).unwrap();
+        assert_eq!(Range::new(Position::new(1, 3), Position::new(1, 7)), range);
+        mock.verify();
+    }
+}
@@ -0,0 +1,438 @@
+//! Session that reconnects to neovim when connection is lost
+//!
+//! ```no_run
+//! use neovim_lib::{NeovimApi, ReconnectOptions, ReconnectingSession};
+//!
+//! let mut session = ReconnectingSession::tcp("127.0.0.1:6666", ReconnectOptions::new());
+//! session.on_connect(|nvim| nvim.subscribe("my_event"));
+//! let states = session.state_events();
+//! session.start();
+//!
+//! for state in states {
+//!     println!("{:?}", state);
+//! }
+//! ```
+use std::cmp;
+use std::io;
+use std::mem;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use std::path::Path;
+
+use rmpv::Value;
+
+use neovim::{CallError, Neovim};
+use rpc::handler::{DefaultHandler, Handler, RequestHandler, Responder};
+use session::Session;
+
+type Connector = Box<dyn FnMut() -> io::Result<Session> + Send>;
+type Setup = Box<dyn FnMut(&mut Neovim) -> Result<(), CallError> + Send>;
+type SharedHandler = Arc<Mutex<Box<dyn Handler + Send>>>;
+
+/// Connection state change event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Connection established and setup functions are finished
+    Connected,
+    /// Connection lost
+    Disconnected,
+    /// Connection attempt failed, next one is made after delay
+    Reconnecting { attempt: u32, delay: Duration },
+    /// Maximum number of attempts reached, no more reconnects
+    Failed,
+}
+
+/// Backoff settings for `ReconnectingSession`
+#[derive(Debug, Clone)]
+pub struct ReconnectOptions {
+    initial_delay: Duration,
+    max_delay: Duration,
+    max_attempts: Option<u32>,
+}
+
+impl ReconnectOptions {
+    pub fn new() -> ReconnectOptions {
+        ReconnectOptions {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::new(10, 0),
+            max_attempts: None,
+        }
+    }
+
+    /// Delay after first failed attempt, doubled after every next one
+    pub fn set_initial_delay(&mut self, delay: Duration) -> &mut Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    pub fn set_max_delay(&mut self, delay: Duration) -> &mut Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Number of failed attempts in a row, after that reconnection stops
+    pub fn set_max_attempts(&mut self, max_attempts: u32) -> &mut Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+}
+
+impl Default for ReconnectOptions {
+    fn default() -> Self {
+        ReconnectOptions::new()
+    }
+}
+
+struct Shared {
+    nvim: Mutex<Option<Neovim>>,
+    connected: Condvar,
+    stopped: Mutex<bool>,
+    stop: Condvar,
+    listeners: Mutex<Vec<mpsc::Sender<ConnectionState>>>,
+}
+
+impl Shared {
+    fn emit(&self, state: ConnectionState) {
+        debug!("Connection state {:?}", state);
+        self.listeners
+            .lock()
+            .unwrap()
+            .retain(|listener| listener.send(state.clone()).is_ok());
+    }
+
+    fn is_stopped(&self) -> bool {
+        *self.stopped.lock().unwrap()
+    }
+
+    /// Returns true if stopped while sleeping
+    fn sleep(&self, dur: Duration) -> bool {
+        let instant = Instant::now();
+        let mut stopped = self.stopped.lock().unwrap();
+        while !*stopped && instant.elapsed() < dur {
+            stopped = self
+                .stop
+                .wait_timeout(stopped, dur - instant.elapsed())
+                .unwrap()
+                .0;
+        }
+        *stopped
+    }
+}
+
+/// Session that reconnects with backoff when connection is lost
+///
+/// Handler is kept between connections, setup functions
+/// registered with `on_connect` are called after every connect.
+pub struct ReconnectingSession {
+    shared: Arc<Shared>,
+    connector: Option<Connector>,
+    setup: Vec<Setup>,
+    handler: SharedHandler,
+    options: ReconnectOptions,
+    supervisor: Option<JoinHandle<()>>,
+}
+
+impl ReconnectingSession {
+    /// Create session, that use `connector` to establish connection
+    pub fn new<C>(connector: C, options: ReconnectOptions) -> ReconnectingSession
+    where
+        C: FnMut() -> io::Result<Session> + Send + 'static,
+    {
+        ReconnectingSession {
+            shared: Arc::new(Shared {
+                nvim: Mutex::new(None),
+                connected: Condvar::new(),
+                stopped: Mutex::new(false),
+                stop: Condvar::new(),
+                listeners: Mutex::new(Vec::new()),
+            }),
+            connector: Some(Box::new(connector)),
+            setup: Vec::new(),
+            handler: Arc::new(Mutex::new(Box::new(DefaultHandler()))),
+            options,
+            supervisor: None,
+        }
+    }
+
+    /// Connect to nvim instance via tcp
+    pub fn tcp(addr: &str, options: ReconnectOptions) -> ReconnectingSession {
+        let addr = addr.to_owned();
+        Self::new(move || Session::new_tcp(&addr), options)
+    }
+
+    #[cfg(unix)]
+    /// Connect to nvim instance via unix socket
+    pub fn unix_socket<P: AsRef<Path>>(path: P, options: ReconnectOptions) -> ReconnectingSession {
+        let path = path.as_ref().to_owned();
+        Self::new(move || Session::new_unix_socket(&path), options)
+    }
+
+    /// Set handler for rpc requests and notifications, must be called before `start`
+    pub fn set_handler<H>(&mut self, handler: H)
+    where
+        H: Handler + Send + 'static,
+    {
+        self.handler = Arc::new(Mutex::new(Box::new(handler)));
+    }
+
+    /// Register function, that is called after every connect, must be called before `start`
+    ///
+    /// Can be used to subscribe to events, attach to buffers, set client info and so on.
+    /// If function returns error connection is considered failed.
+    pub fn on_connect<F>(&mut self, setup: F)
+    where
+        F: FnMut(&mut Neovim) -> Result<(), CallError> + Send + 'static,
+    {
+        self.setup.push(Box::new(setup));
+    }
+
+    /// Receiver for connection state change events
+    pub fn state_events(&self) -> mpsc::Receiver<ConnectionState> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.listeners.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Start connecting in background thread
+    pub fn start(&mut self) {
+        let connector = match self.connector.take() {
+            Some(connector) => connector,
+            None => return,
+        };
+
+        let supervisor = Supervisor {
+            shared: self.shared.clone(),
+            connector,
+            setup: mem::replace(&mut self.setup, Vec::new()),
+            handler: self.handler.clone(),
+            options: self.options.clone(),
+        };
+        self.supervisor = Some(thread::spawn(move || supervisor.run()));
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.nvim.lock().unwrap().is_some()
+    }
+
+    /// Wait connection to be established, returns false on timeout
+    pub fn wait_connected(&self, timeout: Duration) -> bool {
+        let instant = Instant::now();
+        let mut nvim = self.shared.nvim.lock().unwrap();
+        while nvim.is_none() && instant.elapsed() < timeout {
+            nvim = self
+                .shared
+                .connected
+                .wait_timeout(nvim, timeout - instant.elapsed())
+                .unwrap()
+                .0;
+        }
+        nvim.is_some()
+    }
+
+    /// Run `f` with current connection
+    pub fn with<F, T>(&self, f: F) -> Result<T, CallError>
+    where
+        F: FnOnce(&mut Neovim) -> Result<T, CallError>,
+    {
+        match *self.shared.nvim.lock().unwrap() {
+            Some(ref mut nvim) => f(nvim),
+            None => Err(CallError::Disconnected("Not connected".to_owned())),
+        }
+    }
+
+    /// Close current connection and stop reconnecting
+    pub fn shutdown(&mut self) {
+        *self.shared.stopped.lock().unwrap() = true;
+        self.shared.stop.notify_all();
+
+        if let Some(mut nvim) = self.shared.nvim.lock().unwrap().take() {
+            nvim.session.shutdown();
+        }
+        if let Some(supervisor) = self.supervisor.take() {
+            supervisor.join().ok();
+        }
+    }
+}
+
+impl Drop for ReconnectingSession {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+struct Supervisor {
+    shared: Arc<Shared>,
+    connector: Connector,
+    setup: Vec<Setup>,
+    handler: SharedHandler,
+    options: ReconnectOptions,
+}
+
+impl Supervisor {
+    fn run(mut self) {
+        let mut attempt = 0;
+        let mut delay = self.options.initial_delay;
+
+        while !self.shared.is_stopped() {
+            let guard = match self.connect() {
+                Ok(guard) => guard,
+                Err(e) => {
+                    attempt += 1;
+                    debug!("Connection attempt {} failed: {}", attempt, e);
+                    if self.options.max_attempts == Some(attempt) {
+                        self.shared.emit(ConnectionState::Failed);
+                        return;
+                    }
+
+                    self.shared
+                        .emit(ConnectionState::Reconnecting { attempt, delay });
+                    if self.shared.sleep(delay) {
+                        return;
+                    }
+                    delay = cmp::min(delay * 2, self.options.max_delay);
+                    continue;
+                }
+            };
+
+            attempt = 0;
+            delay = self.options.initial_delay;
+            self.shared.emit(ConnectionState::Connected);
+
+            guard.join().ok();
+            self.shared.nvim.lock().unwrap().take();
+
+            if self.shared.is_stopped() {
+                return;
+            }
+            self.shared.emit(ConnectionState::Disconnected);
+        }
+    }
+
+    fn connect(&mut self) -> Result<JoinHandle<()>, String> {
+        let mut session = (self.connector)().map_err(|e| e.to_string())?;
+        session.start_event_loop_handler(ForwardHandler(self.handler.clone()));
+        let guard = session
+            .take_dispatch_guard()
+            .ok_or_else(|| "Event loop not started".to_owned())?;
+
+        let mut nvim = Neovim::new(session);
+        for setup in &mut self.setup {
+            setup(&mut nvim).map_err(|e| e.to_string())?;
+        }
+
+        let mut current = self.shared.nvim.lock().unwrap();
+        if self.shared.is_stopped() {
+            return Err("Session stopped".to_owned());
+        }
+        *current = Some(nvim);
+        self.shared.connected.notify_all();
+
+        Ok(guard)
+    }
+}
+
+/// Handler, that is shared between connections
+struct ForwardHandler(SharedHandler);
+
+impl RequestHandler for ForwardHandler {
+    fn handle_request(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Value> {
+        self.0.lock().unwrap().handle_request(name, args)
+    }
+
+    fn handle_request_deferred(&mut self, name: &str, args: Vec<Value>, responder: Responder) {
+        self.0
+            .lock()
+            .unwrap()
+            .handle_request_deferred(name, args, responder)
+    }
+}
+
+impl Handler for ForwardHandler {
+    fn handle_notify(&mut self, name: &str, args: Vec<Value>) {
+        self.0.lock().unwrap().handle_notify(name, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use neovim_api::NeovimApi;
+    use rpc::model::{self, RpcMessage};
+    use std::net::{TcpListener, TcpStream};
+
+    fn reply_nil(stream: &mut TcpStream) -> String {
+        match model::decode(stream).unwrap() {
+            RpcMessage::RpcRequest { msgid, method, .. } => {
+                model::encode(
+                    stream,
+                    RpcMessage::RpcResponse {
+                        msgid,
+                        error: Value::Nil,
+                        result: Value::Nil,
+                    },
+                )
+                .unwrap();
+                method
+            }
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let mut options = ReconnectOptions::new();
+        options.set_initial_delay(Duration::from_millis(10));
+        let mut session = ReconnectingSession::tcp(&addr, options);
+        session.on_connect(|nvim| nvim.subscribe("event"));
+        let states = session.state_events();
+        session.start();
+
+        let (mut nvim, _) = listener.accept().unwrap();
+        assert_eq!("nvim_subscribe", reply_nil(&mut nvim));
+        assert_eq!(ConnectionState::Connected, states.recv().unwrap());
+        assert!(session.wait_connected(Duration::new(5, 0)));
+
+        drop(nvim);
+        assert_eq!(ConnectionState::Disconnected, states.recv().unwrap());
+
+        let (mut nvim, _) = listener.accept().unwrap();
+        assert_eq!("nvim_subscribe", reply_nil(&mut nvim));
+        assert_eq!(ConnectionState::Connected, states.recv().unwrap());
+
+        session.shutdown();
+        assert!(!session.is_connected());
+        assert_eq!(
+            Err(CallError::Disconnected("Not connected".to_owned())),
+            session.with(|nvim| nvim.command("echo"))
+        );
+    }
+
+    #[test]
+    fn test_max_attempts() {
+        let mut options = ReconnectOptions::new();
+        options
+            .set_initial_delay(Duration::from_millis(1))
+            .set_max_attempts(2);
+        let mut session = ReconnectingSession::new(
+            || Err(io::Error::new(io::ErrorKind::Other, "refused")),
+            options,
+        );
+        let states = session.state_events();
+        session.start();
+
+        assert_eq!(
+            ConnectionState::Reconnecting {
+                attempt: 1,
+                delay: Duration::from_millis(1),
+            },
+            states.recv().unwrap()
+        );
+        assert_eq!(ConnectionState::Failed, states.recv().unwrap());
+    }
+}
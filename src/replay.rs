@@ -0,0 +1,297 @@
+//! Play recorded neovim side of rpc traffic against plugin code
+//!
+//! ```no_run
+//! use neovim_lib::{Neovim, NeovimApi, Player};
+//!
+//! let player = Player::open("bug-report.trace").unwrap();
+//! let (mut session, replay) = player.start();
+//! session.start_event_loop();
+//! let mut nvim = Neovim::new(session);
+//!
+//! nvim.command("echo 'plugin code under test'").unwrap();
+//!
+//! for divergence in replay.finish() {
+//!     println!("{}", divergence);
+//! }
+//! ```
+use std::error::Error;
+use std::fmt;
+use std::io::BufReader;
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use rpc::model::{self, RpcMessage};
+use rpc::pipe::{self, PipeReader, PipeWriter};
+use rpc::record::{self, Direction, Record};
+use session::Session;
+
+/// Recorded client message, that plugin didn't send
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// Index of record in trace
+    pub index: usize,
+    pub expected: RpcMessage,
+    /// Message sent instead, `None` if plugin sent nothing in time
+    pub actual: Option<RpcMessage>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.actual {
+            Some(ref actual) => write!(
+                fmt,
+                "Record {}: expected {:?}, got {:?}",
+                self.index, self.expected, actual
+            ),
+            None => write!(
+                fmt,
+                "Record {}: expected {:?}, got nothing",
+                self.index, self.expected
+            ),
+        }
+    }
+}
+
+/// Replays trace written by `Recorder`
+///
+/// Incoming records are sent to session as fast as possible, outgoing records
+/// are compared with messages sent by session in the same order.
+/// Replay stops when expected message is not sent in time.
+pub struct Player {
+    records: Vec<Record>,
+    timeout: Duration,
+}
+
+impl Player {
+    pub fn new(records: Vec<Record>) -> Player {
+        Player {
+            records,
+            timeout: Duration::new(5, 0),
+        }
+    }
+
+    /// Load trace from file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Player, Box<dyn Error>> {
+        Ok(Player::new(record::read_records(path)?))
+    }
+
+    /// Time to wait for every expected message, 5 seconds by default
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Create session connected to player and start playing
+    pub fn start(&self) -> (Session, Replay) {
+        let (to_client, client_reader) = pipe::pipe();
+        let (client_writer, mut from_client) = pipe::pipe();
+        from_client.set_timeout(Some(self.timeout));
+
+        let records = self.records.clone();
+        let handle = thread::Builder::new()
+            .name("nvim-replay".to_owned())
+            .spawn(move || play(records, to_client, from_client))
+            .expect("Can't spawn replay thread");
+
+        (
+            Session::from_io(client_reader, client_writer),
+            Replay { handle },
+        )
+    }
+}
+
+/// Running replay
+pub struct Replay {
+    handle: JoinHandle<Vec<Divergence>>,
+}
+
+impl Replay {
+    /// Wait until whole trace is played and return found divergences
+    ///
+    /// Session is disconnected after last record.
+    pub fn finish(self) -> Vec<Divergence> {
+        self.handle.join().expect("Replay thread panicked")
+    }
+}
+
+fn play(
+    records: Vec<Record>,
+    mut to_client: PipeWriter,
+    from_client: PipeReader,
+) -> Vec<Divergence> {
+    let mut from_client = BufReader::new(from_client);
+    let mut divergences = Vec::new();
+
+    for (index, record) in records.into_iter().enumerate() {
+        match record.direction {
+            Direction::Incoming => {
+                if let Err(e) = model::encode(&mut to_client, record.message) {
+                    debug!("Session is closed, stop replay: {}", e);
+                    break;
+                }
+            }
+            // peer never got previous outgoing message
+            Direction::Unsent => (),
+            Direction::Outgoing => match model::decode(&mut from_client) {
+                Ok(actual) => {
+                    if actual != record.message {
+                        divergences.push(Divergence {
+                            index,
+                            expected: record.message,
+                            actual: Some(actual),
+                        });
+                    }
+                }
+                Err(e) => {
+                    debug!("Expected message is not read, stop replay: {}", e);
+                    divergences.push(Divergence {
+                        index,
+                        expected: record.message,
+                        actual: None,
+                    });
+                    break;
+                }
+            },
+        }
+    }
+
+    divergences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use neovim::Neovim;
+    use neovim_api::NeovimApi;
+    use rmpv::Value;
+    use rpc::record::Recorder;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn record(direction: Direction, message: RpcMessage) -> Record {
+        Record {
+            direction,
+            time: Duration::new(0, 0),
+            message,
+        }
+    }
+
+    fn trace() -> Vec<Record> {
+        vec![
+            record(
+                Direction::Outgoing,
+                RpcMessage::RpcRequest {
+                    msgid: 0,
+                    method: "nvim_get_current_line".to_owned(),
+                    params: vec![],
+                },
+            ),
+            record(
+                Direction::Incoming,
+                RpcMessage::RpcResponse {
+                    msgid: 0,
+                    error: Value::Nil,
+                    result: Value::from("hello"),
+                },
+            ),
+            record(
+                Direction::Outgoing,
+                RpcMessage::RpcRequest {
+                    msgid: 1,
+                    method: "nvim_set_current_line".to_owned(),
+                    params: vec![Value::from("HELLO")],
+                },
+            ),
+            record(
+                Direction::Incoming,
+                RpcMessage::RpcResponse {
+                    msgid: 1,
+                    error: Value::Nil,
+                    result: Value::Nil,
+                },
+            ),
+        ]
+    }
+
+    fn run_plugin(session: Session) {
+        let mut nvim = Neovim::new(session);
+        let line = nvim.get_current_line().unwrap();
+        nvim.set_current_line(&line.to_uppercase()).unwrap();
+    }
+
+    #[test]
+    fn test_replay_and_record() {
+        let buf = SharedBuf(Arc::new(Mutex::new(Vec::new())));
+
+        let (mut session, replay) = Player::new(trace()).start();
+        session.set_recorder(Recorder::new(buf.clone()));
+        session.start_event_loop();
+        run_plugin(session);
+        assert_eq!(Vec::<Divergence>::new(), replay.finish());
+
+        let data = buf.0.lock().unwrap().clone();
+        let mut reader = &data[..];
+        let mut recorded = Vec::new();
+        while let Some(mut record) = Record::read(&mut reader).unwrap() {
+            record.time = Duration::new(0, 0);
+            recorded.push(record);
+        }
+        assert_eq!(trace(), recorded);
+    }
+
+    #[test]
+    fn test_divergence() {
+        let mut trace = trace();
+        trace[2].message = RpcMessage::RpcRequest {
+            msgid: 1,
+            method: "nvim_set_current_line".to_owned(),
+            params: vec![Value::from("hello!")],
+        };
+
+        let (mut session, replay) = Player::new(trace.clone()).start();
+        session.start_event_loop();
+        run_plugin(session);
+
+        let divergences = replay.finish();
+        assert_eq!(1, divergences.len());
+        assert_eq!(2, divergences[0].index);
+        assert_eq!(trace[2].message, divergences[0].expected);
+        match divergences[0].actual {
+            Some(RpcMessage::RpcRequest { ref params, .. }) => {
+                assert_eq!(vec![Value::from("HELLO")], *params)
+            }
+            ref actual => panic!("Unexpected message {:?}", actual),
+        }
+    }
+
+    #[test]
+    fn test_missing_message() {
+        let (mut session, replay) = Player::new(trace())
+            .set_timeout(Duration::from_millis(100))
+            .start();
+        session.start_event_loop();
+        {
+            let mut nvim = Neovim::new(session);
+            nvim.get_current_line().unwrap();
+        }
+
+        let divergences = replay.finish();
+        assert_eq!(1, divergences.len());
+        assert_eq!(2, divergences[0].index);
+        assert_eq!(None, divergences[0].actual);
+    }
+}
@@ -0,0 +1,230 @@
+//! Call arguments, serialized by reference straight into transport
+use std::io::{self, Write};
+
+use rmp::encode::{write_array_len, write_bin, write_bool, write_map_len, write_str};
+use rmpv::decode::read_value;
+use rmpv::encode::write_value;
+use rmpv::Value;
+
+/// Argument, that can be written as msgpack without converting to `Value`
+pub trait EncodeArg {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()>;
+}
+
+fn write_err<E: ::std::error::Error + Send + Sync + 'static>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+impl<T: EncodeArg + ?Sized> EncodeArg for &T {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        (**self).encode_arg(writer)
+    }
+}
+
+impl EncodeArg for Value {
+    fn encode_arg(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        write_value(&mut writer, self).map_err(write_err)
+    }
+}
+
+impl EncodeArg for str {
+    fn encode_arg(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        write_str(&mut writer, self).map_err(write_err)
+    }
+}
+
+impl EncodeArg for String {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        self.as_str().encode_arg(writer)
+    }
+}
+
+impl EncodeArg for i64 {
+    fn encode_arg(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        // same encoding as `Value::from(i64)`, positive numbers are written as unsigned
+        write_value(&mut writer, &Value::from(*self)).map_err(write_err)
+    }
+}
+
+impl EncodeArg for bool {
+    fn encode_arg(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        write_bool(&mut writer, *self).map_err(write_err)
+    }
+}
+
+impl EncodeArg for (i64, i64) {
+    fn encode_arg(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        write_array_len(&mut writer, 2).map_err(write_err)?;
+        self.0.encode_arg(writer)?;
+        self.1.encode_arg(writer)
+    }
+}
+
+fn encode_seq<T: EncodeArg>(items: &[T], mut writer: &mut dyn Write) -> io::Result<()> {
+    write_array_len(&mut writer, items.len() as u32).map_err(write_err)?;
+    for item in items {
+        item.encode_arg(writer)?;
+    }
+    Ok(())
+}
+
+impl EncodeArg for [Value] {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        encode_seq(self, writer)
+    }
+}
+
+impl EncodeArg for Vec<Value> {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        encode_seq(self, writer)
+    }
+}
+
+impl EncodeArg for [String] {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        encode_seq(self, writer)
+    }
+}
+
+impl EncodeArg for Vec<String> {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        encode_seq(self, writer)
+    }
+}
+
+/// Raw bytes are sent as binary, neovim accepts it everywhere string is expected
+impl EncodeArg for [u8] {
+    fn encode_arg(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        write_bin(&mut writer, self).map_err(write_err)
+    }
+}
+
+impl EncodeArg for Vec<u8> {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        self.as_slice().encode_arg(writer)
+    }
+}
+
+impl EncodeArg for [Vec<u8>] {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        encode_seq(self, writer)
+    }
+}
+
+impl EncodeArg for Vec<Vec<u8>> {
+    fn encode_arg(&self, writer: &mut dyn Write) -> io::Result<()> {
+        encode_seq(self, writer)
+    }
+}
+
+impl EncodeArg for Vec<(Value, Value)> {
+    fn encode_arg(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        write_map_len(&mut writer, self.len() as u32).map_err(write_err)?;
+        for (key, val) in self {
+            key.encode_arg(writer)?;
+            val.encode_arg(writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Borrowed call arguments
+///
+/// Arguments are written directly to transport when request is sent,
+/// so large values like `Vec<String>` of buffer lines are never copied.
+#[derive(Default)]
+pub struct CallArgs<'a> {
+    items: Vec<&'a dyn EncodeArg>,
+}
+
+impl<'a> CallArgs<'a> {
+    pub fn new() -> CallArgs<'a> {
+        CallArgs { items: Vec::new() }
+    }
+
+    /// Borrow already converted values
+    pub fn from_values(values: &'a [Value]) -> CallArgs<'a> {
+        CallArgs {
+            items: values.iter().map(|val| val as &dyn EncodeArg).collect(),
+        }
+    }
+
+    pub fn push(&mut self, arg: &'a dyn EncodeArg) -> &mut Self {
+        self.items.push(arg);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Write arguments as msgpack array
+    pub fn write_to(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        write_array_len(&mut writer, self.items.len() as u32).map_err(write_err)?;
+        for item in &self.items {
+            item.encode_arg(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Convert to values, for backends and hooks that need them
+    pub fn to_values(&self) -> Vec<Value> {
+        self.items
+            .iter()
+            .map(|item| {
+                let mut data = Vec::new();
+                item.encode_arg(&mut data).expect("Can't write to vec");
+                read_value(&mut &data[..]).expect("Can't decode encoded argument")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rpc::model::IntoVal;
+
+    #[test]
+    fn test_same_encoding_as_values() {
+        let name = "line";
+        let lines = vec!["a".to_owned(), "ü".to_owned()];
+        let opts = vec![(Value::from("rgb"), Value::from(true))];
+        let handle = Value::Ext(0, vec![1]);
+        let numbers = [0, 127, 128, -1, -33, i64::MAX, i64::MIN];
+
+        let mut args = CallArgs::new();
+        args.push(&handle)
+            .push(&name)
+            .push(&lines)
+            .push(&opts)
+            .push(&false)
+            .push(&(1, -2));
+        for n in &numbers {
+            args.push(n);
+        }
+
+        let mut values = vec![
+            handle.clone(),
+            name.into_val(),
+            lines.clone().into_val(),
+            opts.clone().into_val(),
+            false.into_val(),
+            (1, -2).into_val(),
+        ];
+        values.extend(numbers.iter().map(|n| n.into_val()));
+
+        let mut data = Vec::new();
+        args.write_to(&mut data).unwrap();
+        let mut expected = Vec::new();
+        write_value(&mut expected, &Value::from(values.clone())).unwrap();
+
+        assert_eq!(expected, data);
+        assert_eq!(values, args.to_values());
+        assert_eq!(values.len(), args.len());
+    }
+}
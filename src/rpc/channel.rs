@@ -0,0 +1,398 @@
+//! Bounded channel for notifications
+use std::collections::VecDeque;
+use std::sync::mpsc::{RecvError, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use rmpv::Value;
+
+use super::handler::{Handler, RequestHandler, Responder};
+
+type Notification = (String, Vec<Value>);
+
+/// What to do with new notification when channel is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until receiver takes a message.
+    /// Dispatch thread is blocked, so responses are delayed too.
+    Block,
+    /// Remove the oldest queued notification
+    DropOldest,
+    /// Queued notification with the same method is replaced by new one,
+    /// if there is none the oldest is removed
+    Coalesce,
+}
+
+/// Options for bounded notification channel
+#[derive(Debug, Clone)]
+pub struct ChannelOptions {
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+impl ChannelOptions {
+    pub fn new() -> ChannelOptions {
+        ChannelOptions {
+            capacity: 1024,
+            policy: OverflowPolicy::Block,
+        }
+    }
+
+    /// Max number of queued notifications, 1024 by default
+    pub fn set_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// `OverflowPolicy::Block` by default
+    pub fn set_policy(&mut self, policy: OverflowPolicy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl Default for ChannelOptions {
+    fn default() -> Self {
+        ChannelOptions::new()
+    }
+}
+
+struct State {
+    queue: VecDeque<Notification>,
+    sender_alive: bool,
+    receiver_alive: bool,
+    dropped: u64,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    options: ChannelOptions,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+/// Create bounded channel
+pub fn bounded(options: &ChannelOptions) -> (NotifySender, NotifyReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            sender_alive: true,
+            receiver_alive: true,
+            dropped: 0,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+        options: options.clone(),
+    });
+
+    (
+        NotifySender {
+            shared: shared.clone(),
+        },
+        NotifyReceiver { shared },
+    )
+}
+
+/// Sending half of bounded channel
+///
+/// Notifications are silently dropped after receiver is gone.
+pub struct NotifySender {
+    shared: Arc<Shared>,
+}
+
+impl NotifySender {
+    pub fn send(&self, notification: Notification) {
+        let shared = &self.shared;
+        let mut state = shared.lock();
+        let capacity = shared.options.capacity;
+
+        match shared.options.policy {
+            OverflowPolicy::Block => {
+                while state.receiver_alive && state.queue.len() >= capacity {
+                    state = shared.not_full.wait(state).unwrap();
+                }
+            }
+            OverflowPolicy::DropOldest => {
+                if state.queue.len() >= capacity {
+                    state.queue.pop_front();
+                    state.dropped += 1;
+                }
+            }
+            OverflowPolicy::Coalesce => {
+                if state.queue.len() >= capacity {
+                    let same = state
+                        .queue
+                        .iter()
+                        .position(|queued| queued.0 == notification.0);
+                    match same {
+                        Some(pos) => state.queue.remove(pos),
+                        None => state.queue.pop_front(),
+                    };
+                    state.dropped += 1;
+                }
+            }
+        }
+
+        if !state.receiver_alive {
+            debug!(
+                "Notification receiver is dropped, {} ignored",
+                notification.0
+            );
+            return;
+        }
+
+        state.queue.push_back(notification);
+        shared.not_empty.notify_one();
+    }
+}
+
+impl Drop for NotifySender {
+    fn drop(&mut self) {
+        self.shared.lock().sender_alive = false;
+        self.shared.not_empty.notify_all();
+    }
+}
+
+/// Receiving half of bounded channel
+///
+/// Works like `mpsc::Receiver`, receiving fails when session is closed
+/// and all queued notifications are taken.
+pub struct NotifyReceiver {
+    shared: Arc<Shared>,
+}
+
+impl NotifyReceiver {
+    pub fn recv(&self) -> Result<Notification, RecvError> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(notification) = self.pop(&mut state) {
+                return Ok(notification);
+            }
+            if !state.sender_alive {
+                return Err(RecvError);
+            }
+            state = self.shared.not_empty.wait(state).unwrap();
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<Notification, TryRecvError> {
+        let mut state = self.shared.lock();
+        match self.pop(&mut state) {
+            Some(notification) => Ok(notification),
+            None if state.sender_alive => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Notification, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(notification) = self.pop(&mut state) {
+                return Ok(notification);
+            }
+            if !state.sender_alive {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .shared
+                .not_empty
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Blocking iterator, that ends when session is closed
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { receiver: self }
+    }
+
+    /// Number of notifications dropped or replaced because of overflow policy
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().dropped
+    }
+
+    fn pop(&self, state: &mut State) -> Option<Notification> {
+        let notification = state.queue.pop_front();
+        if notification.is_some() {
+            self.shared.not_full.notify_one();
+        }
+        notification
+    }
+}
+
+impl Drop for NotifyReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver_alive = false;
+        state.queue.clear();
+        self.shared.not_full.notify_all();
+    }
+}
+
+pub struct Iter<'a> {
+    receiver: &'a NotifyReceiver,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Notification;
+
+    fn next(&mut self) -> Option<Notification> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'a> IntoIterator for &'a NotifyReceiver {
+    type Item = Notification;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Same as `ChannelHandler`, but notifications are sent to bounded channel
+pub struct BoundedChannelHandler<H: RequestHandler> {
+    sender: NotifySender,
+    request_handler: H,
+}
+
+impl<H: RequestHandler> BoundedChannelHandler<H> {
+    pub fn new(request_handler: H, options: &ChannelOptions) -> (Self, NotifyReceiver) {
+        let (sender, receiver) = bounded(options);
+        (
+            BoundedChannelHandler {
+                sender,
+                request_handler,
+            },
+            receiver,
+        )
+    }
+}
+
+impl<H: RequestHandler> Handler for BoundedChannelHandler<H> {
+    fn handle_notify(&mut self, name: &str, args: Vec<Value>) {
+        self.sender.send((name.to_owned(), args))
+    }
+}
+
+impl<H: RequestHandler> RequestHandler for BoundedChannelHandler<H> {
+    fn handle_request(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Value> {
+        self.request_handler.handle_request(name, args)
+    }
+
+    fn handle_request_deferred(&mut self, name: &str, args: Vec<Value>, responder: Responder) {
+        self.request_handler
+            .handle_request_deferred(name, args, responder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn notification(name: &str, arg: i64) -> Notification {
+        (name.to_owned(), vec![Value::from(arg)])
+    }
+
+    fn received(receiver: &NotifyReceiver) -> Vec<Notification> {
+        let mut res = Vec::new();
+        while let Ok(notification) = receiver.try_recv() {
+            res.push(notification);
+        }
+        res
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let (sender, receiver) = bounded(
+            ChannelOptions::new()
+                .set_capacity(2)
+                .set_policy(OverflowPolicy::DropOldest),
+        );
+        for i in 0..3 {
+            sender.send(notification("redraw", i));
+        }
+
+        assert_eq!(
+            vec![notification("redraw", 1), notification("redraw", 2)],
+            received(&receiver)
+        );
+        assert_eq!(1, receiver.dropped());
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let (sender, receiver) = bounded(
+            ChannelOptions::new()
+                .set_capacity(2)
+                .set_policy(OverflowPolicy::Coalesce),
+        );
+        sender.send(notification("cursor", 1));
+        sender.send(notification("mode", 1));
+        sender.send(notification("cursor", 2));
+        sender.send(notification("scroll", 1));
+
+        assert_eq!(
+            vec![notification("cursor", 2), notification("scroll", 1)],
+            received(&receiver)
+        );
+        assert_eq!(2, receiver.dropped());
+    }
+
+    #[test]
+    fn test_coalesce_not_full() {
+        let (sender, receiver) = bounded(
+            ChannelOptions::new()
+                .set_capacity(3)
+                .set_policy(OverflowPolicy::Coalesce),
+        );
+        sender.send(notification("redraw", 1));
+        sender.send(notification("redraw", 2));
+
+        assert_eq!(
+            vec![notification("redraw", 1), notification("redraw", 2)],
+            received(&receiver)
+        );
+        assert_eq!(0, receiver.dropped());
+    }
+
+    #[test]
+    fn test_block() {
+        let (sender, receiver) = bounded(ChannelOptions::new().set_capacity(1));
+
+        let sender_thread = thread::spawn(move || {
+            for i in 0..3 {
+                sender.send(notification("redraw", i));
+            }
+        });
+
+        for i in 0..3 {
+            assert_eq!(notification("redraw", i), receiver.recv().unwrap());
+        }
+        sender_thread.join().unwrap();
+        assert_eq!(Err(RecvError), receiver.recv());
+    }
+
+    #[test]
+    fn test_receiver_dropped() {
+        let (sender, receiver) = bounded(ChannelOptions::new().set_capacity(1));
+        sender.send(notification("redraw", 0));
+
+        let sender_thread = thread::spawn(move || sender.send(notification("redraw", 1)));
+        drop(receiver);
+        sender_thread.join().unwrap();
+    }
+}
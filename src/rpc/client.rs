@@ -1,29 +1,158 @@
+use std::collections::VecDeque;
 use std::error::Error;
-use std::io::{BufReader, BufWriter, Read, Write};
-use std::sync::{mpsc, Arc, Mutex};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::sync::{mpsc, Arc, Mutex, Weak};
 use std::thread;
-use std::thread::JoinHandle;
+use std::thread::{JoinHandle, ThreadId};
 use std::time::{Duration, Instant};
 
-use super::handler::{self, DefaultHandler, Handler, RequestHandler};
+use super::args::CallArgs;
+use super::channel::{BoundedChannelHandler, ChannelOptions, NotifyReceiver};
+use super::decoder::{self, DecodeLimits, ProtocolError};
+use super::dispatch::{Dispatcher, Inline};
+use super::handler::{self, DefaultHandler, Handler, RequestHandler, Responder, ResponseSink};
+use super::poll::{PollReader, ReadyCheck};
+use super::pool::{Pool, PoolOptions};
+use super::observer::{CancelEvent, Counter, Observer, RequestEvent, ResponseEvent};
+use super::record::{Direction, Tap};
+use super::writer::{self, Sink, WriterOptions, WriterThread};
+use neovim::{map_generic_error, CallError};
 use rmpv::Value;
 
 use super::model;
 
-type Callback = Box<FnMut(Result<Value, Value>) + Send + 'static>;
+type Callback = Box<dyn FnMut(Result<Value, CallError>) + Send + 'static>;
 type Queue = Arc<Mutex<Vec<(u64, Sender)>>>;
+type Disconnected = Arc<Mutex<Option<CallError>>>;
+type Writer<W> = Arc<Mutex<Option<Sink<W>>>>;
+type DisconnectContext = Arc<dyn Fn() -> Option<String> + Send + Sync>;
+type ShutdownHook = Box<dyn FnMut() + Send>;
+
+/// Messages from reader thread, shared between dispatch thread and sync calls made from it
+///
+/// Reading on separate thread lets nested calls wait for response with timeout.
+struct Incoming {
+    receiver: Mutex<mpsc::Receiver<Result<model::RpcMessage, ProtocolError>>>,
+    /// Requests and notifications read by nested calls, handled by dispatch thread later
+    backlog: Mutex<VecDeque<model::RpcMessage>>,
+}
+
+impl Incoming {
+    fn spawn<R>(mut reader: BufReader<R>, tap: Option<Tap>, limits: DecodeLimits) -> Self
+    where
+        R: Read + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("nvim-reader".to_owned())
+            .spawn(move || loop {
+                let res = decoder::read_message(&mut reader, &limits);
+                if let (Ok(msg), Some(tap)) = (&res, &tap) {
+                    tap(Direction::Incoming, msg);
+                }
+                let failed = res.is_err();
+                if sender.send(res).is_err() || failed {
+                    return;
+                }
+            })
+            .expect("Can't spawn reader thread");
+
+        Incoming {
+            receiver: Mutex::new(receiver),
+            backlog: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn next(&self) -> Result<model::RpcMessage, Box<dyn Error>> {
+        let pending = self.backlog.lock().unwrap().pop_front();
+        match pending {
+            Some(msg) => Ok(msg),
+            None => self
+                .read(None)
+                .map(|msg| msg.expect("No message without timeout")),
+        }
+    }
+
+    /// Wait next message from reader thread, `None` on timeout
+    fn read(&self, timeout: Option<Duration>) -> Result<Option<model::RpcMessage>, Box<dyn Error>> {
+        let receiver = self.receiver.lock().unwrap();
+        let res = match timeout {
+            Some(timeout) => match receiver.recv_timeout(timeout) {
+                Ok(res) => res,
+                Err(mpsc::RecvTimeoutError::Timeout) => return Ok(None),
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err("Reader thread stopped".into())
+                }
+            },
+            None => receiver.recv().map_err(|_| "Reader thread stopped")?,
+        };
+        Ok(Some(res?))
+    }
+}
+
+/// State of client, that runs without dispatch thread
+struct Polled<R> {
+    reader: PollReader<R>,
+    dispatcher: Box<dyn Dispatcher + Send>,
+    /// Requests and notifications read by sync calls, handled by `process_pending`
+    backlog: VecDeque<model::RpcMessage>,
+}
 
 enum Sender {
-    Sync(mpsc::Sender<Result<Value, Value>>),
+    Sync(mpsc::Sender<Result<Value, CallError>>),
     Async(Callback),
+    /// Call, that is reported to observer when finished
+    Observed(Box<Sender>, ObservedCall),
 }
 
 impl Sender {
-    fn send(self, res: Result<Value, Value>) {
+    fn send(self, res: Result<Value, CallError>) {
         match self {
-            Sender::Sync(sender) => sender.send(res).unwrap(),
+            Sender::Sync(sender) => sender.send(res).unwrap_or(()),
             Sender::Async(mut cb) => cb(res),
+            Sender::Observed(sender, call) => {
+                call.finish(&res);
+                sender.send(res);
+            }
+        };
+    }
+
+    /// Drop call without result, observer is told about cancellation
+    fn cancel(self) {
+        if let Sender::Observed(_, call) = self {
+            call.observer.on_cancel(&CancelEvent {
+                msgid: call.msgid,
+                method: &call.method,
+                elapsed: call.started.elapsed(),
+            });
+        }
+    }
+}
+
+struct ObservedCall {
+    observer: Arc<dyn Observer>,
+    msgid: u64,
+    method: String,
+    started: Instant,
+}
+
+impl ObservedCall {
+    fn finish(&self, res: &Result<Value, CallError>) {
+        let size = match *res {
+            Ok(ref val) => {
+                let mut counter = Counter::new(io::sink());
+                rmpv::encode::write_value(&mut counter, val).ok();
+                counter.size
+            }
+            Err(_) => 0,
         };
+        self.observer.on_response(&ResponseEvent {
+            msgid: self.msgid,
+            method: &self.method,
+            elapsed: self.started.elapsed(),
+            size,
+            result: res.as_ref().map(|_| ()),
+        });
     }
 }
 
@@ -33,11 +162,23 @@ where
     W: Write + Send + 'static,
 {
     reader: Option<BufReader<R>>,
-    writer: Arc<Mutex<BufWriter<W>>>,
+    incoming: Option<Arc<Incoming>>,
+    dispatch_thread: Option<ThreadId>,
+    polled: Option<Polled<R>>,
+    writer: Writer<W>,
+    writer_thread: Option<WriterThread>,
     dispatch_guard: Option<JoinHandle<()>>,
+    dispatch_done: Option<mpsc::Receiver<()>>,
     event_loop_started: bool,
+    disconnected: Disconnected,
     queue: Queue,
     msgid_counter: u64,
+    disconnect_context: Option<DisconnectContext>,
+    shutdown_hook: Option<ShutdownHook>,
+    shutdown_timeout: Duration,
+    tap: Option<Tap>,
+    observer: Option<Arc<dyn Observer>>,
+    decode_limits: DecodeLimits,
 }
 
 impl<R, W> Client<R, W>
@@ -45,10 +186,8 @@ where
     R: Read + Send + 'static,
     W: Write + Send + 'static,
 {
-    pub fn take_dispatch_guard(&mut self) -> JoinHandle<()> {
-        self.dispatch_guard
-            .take()
-            .expect("Can only take join handle after running event loop")
+    pub fn take_dispatch_guard(&mut self) -> Option<JoinHandle<()>> {
+        self.dispatch_guard.take()
     }
 
     pub fn start_event_loop_channel_handler<H>(
@@ -59,15 +198,20 @@ where
         H: RequestHandler + Send + 'static,
     {
         let (handler, reciever) = handler::channel(request_handler);
+        self.start_dispatch(handler);
+        reciever
+    }
 
-        self.dispatch_guard = Some(Self::dispatch_thread(
-            self.queue.clone(),
-            self.reader.take().unwrap(),
-            self.writer.clone(),
-            handler,
-        ));
-        self.event_loop_started = true;
-
+    pub fn start_event_loop_bounded_channel_handler<H>(
+        &mut self,
+        request_handler: H,
+        options: &ChannelOptions,
+    ) -> NotifyReceiver
+    where
+        H: RequestHandler + Send + 'static,
+    {
+        let (handler, reciever) = BoundedChannelHandler::new(request_handler, options);
+        self.start_dispatch(handler);
         reciever
     }
 
@@ -75,22 +219,128 @@ where
     where
         H: Handler + Send + 'static,
     {
-        self.dispatch_guard = Some(Self::dispatch_thread(
-            self.queue.clone(),
-            self.reader.take().unwrap(),
-            self.writer.clone(),
-            handler,
-        ));
-        self.event_loop_started = true;
+        self.start_dispatch(handler);
     }
 
     pub fn start_event_loop(&mut self) {
-        self.dispatch_guard = Some(Self::dispatch_thread(
-            self.queue.clone(),
+        self.start_dispatch(DefaultHandler());
+    }
+
+    pub fn start_event_loop_pool<H>(&mut self, handler: H, options: &PoolOptions)
+    where
+        H: Handler + Clone + Send + 'static,
+    {
+        self.start_dispatcher(Pool::new(handler, options));
+    }
+
+    /// Start without dispatch thread
+    ///
+    /// Messages are read only by `process_pending` and sync calls on the caller thread.
+    pub fn start_poll_handler<H>(&mut self, handler: H, ready: ReadyCheck)
+    where
+        H: Handler + Send + 'static,
+    {
+        let mut reader = PollReader::new(self.reader.take().unwrap().into_inner(), ready);
+        reader.set_decode_limits(self.decode_limits.clone());
+        self.polled = Some(Polled {
+            reader,
+            dispatcher: Box::new(Inline(handler)),
+            backlog: VecDeque::new(),
+        });
+        self.event_loop_started = true;
+    }
+
+    /// Read messages, that are available now, and dispatch them on current thread
+    ///
+    /// Returns number of processed messages.
+    pub fn process_pending(&mut self) -> Result<usize, CallError> {
+        if self.polled.is_none() {
+            return Err(CallError::GenericError(
+                "Poll mode is not started".to_owned(),
+            ));
+        }
+
+        let mut count = self.pump(Some(Duration::new(0, 0)))?;
+
+        let sink = response_sink(&self.queue, &self.writer, &self.tap, &self.disconnected);
+        let polled = self.polled.as_mut().unwrap();
+        while let Some(msg) = polled.backlog.pop_front() {
+            dispatch_msg(&mut *polled.dispatcher, &self.queue, &sink, msg);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Read messages in poll mode, responses are delivered immediately,
+    /// other messages wait in backlog
+    fn pump(&mut self, timeout: Option<Duration>) -> Result<usize, CallError> {
+        if let Some(err) = self.disconnected.lock().unwrap().clone() {
+            return Err(err);
+        }
+
+        let polled = self.polled.as_mut().unwrap();
+        match polled.reader.read(timeout) {
+            Ok(msgs) => {
+                let mut count = 0;
+                for msg in msgs {
+                    if let Some(ref tap) = self.tap {
+                        tap(Direction::Incoming, &msg);
+                    }
+                    match msg {
+                        model::RpcMessage::RpcResponse {
+                            msgid,
+                            result,
+                            error,
+                        } => {
+                            deliver_response(&self.queue, msgid, result, error);
+                            count += 1;
+                        }
+                        msg => polled.backlog.push_back(msg),
+                    }
+                }
+                Ok(count)
+            }
+            Err(e) => {
+                let err = read_error(e, &self.disconnect_context);
+                error!("{}", err);
+                disconnect(&self.queue, &self.disconnected, err.clone());
+                Err(err)
+            }
+        }
+    }
+
+    fn start_dispatch<H>(&mut self, handler: H)
+    where
+        H: Handler + Send + 'static,
+    {
+        self.start_dispatcher(Inline(handler));
+    }
+
+    fn start_dispatcher<D>(&mut self, dispatcher: D)
+    where
+        D: Dispatcher + Send + 'static,
+    {
+        let (done_sender, done_receiver) = mpsc::channel();
+        let incoming = Arc::new(Incoming::spawn(
             self.reader.take().unwrap(),
-            self.writer.clone(),
-            DefaultHandler(),
+            self.tap.clone(),
+            self.decode_limits.clone(),
         ));
+        let sink = response_sink(&self.queue, &self.writer, &self.tap, &self.disconnected);
+        let guard = Self::dispatch_thread(
+            self.queue.clone(),
+            incoming.clone(),
+            sink,
+            self.disconnected.clone(),
+            self.disconnect_context.clone(),
+            done_sender,
+            dispatcher,
+        );
+        self.incoming = Some(incoming);
+        self.dispatch_thread = Some(guard.thread().id());
+        self.dispatch_guard = Some(guard);
+        self.dispatch_done = Some(done_receiver);
         self.event_loop_started = true;
     }
 
@@ -98,25 +348,164 @@ where
         let queue = Arc::new(Mutex::new(Vec::new()));
         Client {
             reader: Some(BufReader::new(reader)),
-            writer: Arc::new(Mutex::new(BufWriter::new(writer))),
+            incoming: None,
+            dispatch_thread: None,
+            polled: None,
+            writer: Arc::new(Mutex::new(Some(Sink::Direct(BufWriter::new(writer))))),
+            writer_thread: None,
             msgid_counter: 0,
             queue: queue.clone(),
             dispatch_guard: None,
+            dispatch_done: None,
             event_loop_started: false,
+            disconnected: Arc::new(Mutex::new(None)),
+            disconnect_context: None,
+            shutdown_hook: None,
+            shutdown_timeout: Duration::new(1, 0),
+            tap: None,
+            observer: None,
+            decode_limits: DecodeLimits::default(),
         }
     }
 
-    pub fn call_async(&mut self, method: String, args: Vec<Value>, cb: Option<Callback>) {
-        if !self.event_loop_started {
+    /// Set function, that provide additional information for disconnect error
+    ///
+    /// Must be called before event loop is started.
+    pub fn set_disconnect_context<F>(&mut self, context: F)
+    where
+        F: Fn() -> Option<String> + Send + Sync + 'static,
+    {
+        self.disconnect_context = Some(Arc::new(context));
+    }
+
+    /// Set function, that unblocks reader after write side is closed,
+    /// for example by shutting down socket
+    pub fn set_shutdown_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.shutdown_hook = Some(Box::new(hook));
+    }
+
+    /// Set function, that is called for every message read or written,
+    /// see `Recorder`
+    ///
+    /// Must be called before event loop is started.
+    pub fn set_tap(&mut self, tap: Tap) {
+        self.tap = Some(tap);
+    }
+
+    /// Set observer, that is notified about every call, see `Metrics`
+    pub fn set_observer(&mut self, observer: Arc<dyn Observer>) {
+        self.observer = Some(observer);
+    }
+
+    /// Set size and nesting limits for incoming messages
+    ///
+    /// Connection is closed when message exceeds limits.
+    /// Must be called before event loop is started.
+    pub fn set_decode_limits(&mut self, limits: DecodeLimits) {
+        self.decode_limits = limits;
+    }
+
+    /// Write messages on separate thread, that coalesces bursts into fewer writes
+    ///
+    /// Sync calls and responses to neovim requests are flushed right away,
+    /// async calls are flushed when burst ends.
+    pub fn start_writer_thread(&mut self, options: &WriterOptions) -> io::Result<()> {
+        let mut sink = self.writer.lock().unwrap();
+        let writer = match sink.take() {
+            Some(Sink::Direct(writer)) => writer,
+            Some(queued) => {
+                *sink = Some(queued);
+                return Ok(());
+            }
+            None => return Err(io::Error::new(io::ErrorKind::NotConnected, "Session closed")),
+        };
+
+        let queue = self.queue.clone();
+        let disconnected = self.disconnected.clone();
+        let (sender, thread) = writer::spawn(writer, options, move |e| {
+            let err = format!("Error sending message: {}", e);
+            error!("{}", err);
+            disconnect(&queue, &disconnected, CallError::Disconnected(err));
+        })?;
+        *sink = Some(Sink::Queued(sender));
+        self.writer_thread = Some(thread);
+        Ok(())
+    }
+
+    /// Time to wait dispatch thread to finish on shutdown, after that thread is detached
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) {
+        self.shutdown_timeout = timeout;
+    }
+
+    /// Connection is lost or closed, no more calls can be made
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected.lock().unwrap().is_some()
+    }
+
+    /// Close connection and stop dispatch thread
+    ///
+    /// All pending calls are finished with "Session closed" error.
+    pub fn shutdown(&mut self) {
+        disconnect(
+            &self.queue,
+            &self.disconnected,
+            CallError::Disconnected("Session closed".to_owned()),
+        );
+
+        match self.writer.lock().unwrap().take() {
+            Some(Sink::Direct(mut writer)) => {
+                writer.flush().ok();
+            }
+            // dropped sender stops writer thread after queued messages are written
+            Some(Sink::Queued(_)) | None => (),
+        }
+        if let Some(thread) = self.writer_thread.take() {
+            thread.join(self.shutdown_timeout);
+        }
+        self.reader.take();
+
+        if let Some(mut hook) = self.shutdown_hook.take() {
+            hook();
+        }
+
+        if let (Some(guard), Some(done)) = (self.dispatch_guard.take(), self.dispatch_done.take()) {
+            match done.recv_timeout(self.shutdown_timeout) {
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    warn!("Dispatch thread is still running, detach it");
+                }
+                _ => {
+                    guard.join().ok();
+                }
+            }
+        }
+    }
+
+    pub fn call_async(
+        &mut self,
+        method: String,
+        args: Vec<Value>,
+        cb: Option<Callback>,
+    ) -> CancelHandle {
+        let msgid = if !self.event_loop_started {
             if let Some(mut cb) = cb {
-                cb(Err(Value::from("Event loop not started")));
+                cb(Err(CallError::GenericError(
+                    "Event loop not started".to_owned(),
+                )));
             } else {
                 error!("Event loop not started");
             }
-            return;
-        }
+            None
+        } else {
+            Some(self.send_msg_async(method, args, cb))
+        };
 
-        self.send_msg_async(method, args, cb);
+        CancelHandle {
+            queue: Arc::downgrade(&self.queue),
+            msgid,
+        }
     }
 
     pub fn call_timeout(
@@ -124,70 +513,143 @@ where
         method: &str,
         args: Vec<Value>,
         dur: Duration,
-    ) -> Result<Value, Value> {
+    ) -> Result<Value, CallError> {
+        self.call_args_timeout(method, &CallArgs::from_values(&args), dur)
+    }
+
+    fn call_args_timeout(
+        &mut self,
+        method: &str,
+        args: &CallArgs,
+        dur: Duration,
+    ) -> Result<Value, CallError> {
         if !self.event_loop_started {
-            return Err(Value::from("Event loop not started"));
+            return Err(CallError::GenericError("Event loop not started".to_owned()));
+        }
+        if self.is_dispatch_thread() {
+            return self.call_nested(method, args, Some(dur));
+        }
+        if self.polled.is_some() {
+            return self.call_polled(method, args, Some(dur));
         }
 
         let instant = Instant::now();
         let delay = Duration::from_millis(1);
 
-        let receiver = self.send_msg(method, args);
+        let (msgid, receiver) = self.send_msg(method, args);
 
         loop {
             match receiver.try_recv() {
                 Err(mpsc::TryRecvError::Empty) => {
                     thread::sleep(delay);
                     if instant.elapsed() >= dur {
-                        return Err(Value::from(format!("Wait timeout ({})", method)));
+                        return Err(self.timed_out(msgid, method));
                     }
                 }
                 Err(mpsc::TryRecvError::Disconnected) => {
-                    return Err(Value::from(format!("Channel disconnected ({})", method)))
+                    return Err(CallError::GenericError(format!(
+                        "Channel disconnected ({})",
+                        method
+                    )))
                 }
                 Ok(val) => return val,
             };
         }
     }
 
-    fn send_msg_async(&mut self, method: String, params: Vec<Value>, cb: Option<Callback>) {
-        let msgid = self.msgid_counter;
-        self.msgid_counter += 1;
+    fn send_msg_async(&mut self, method: String, params: Vec<Value>, cb: Option<Callback>) -> u64 {
+        // keep call without callback in queue too, so its response is not reported as unknown
+        let cb = cb.unwrap_or_else(|| {
+            Box::new(|res| {
+                if let Err(err) = res {
+                    debug!("Async call failed: {}", err);
+                }
+            })
+        });
+        self.send_request(&method, &CallArgs::from_values(&params), Some(Sender::Async(cb)))
+    }
 
-        let req = model::RpcMessage::RpcRequest {
-            msgid,
-            method,
-            params,
-        };
+    fn send_msg(
+        &mut self,
+        method: &str,
+        args: &CallArgs,
+    ) -> (u64, mpsc::Receiver<Result<Value, CallError>>) {
+        let (sender, receiver) = mpsc::channel();
+        let msgid = self.send_request(method, args, Some(Sender::Sync(sender)));
+        (msgid, receiver)
+    }
 
-        if let Some(cb) = cb {
-            self.queue.lock().unwrap().push((msgid, Sender::Async(cb)));
+    /// Forget timed out call, so late response is ignored
+    fn timed_out(&self, msgid: u64, method: &str) -> CallError {
+        let err = CallError::GenericError(format!("Wait timeout ({})", method));
+        // caller does not wait anymore, only observer sees the error
+        if let Some(sender) = find_sender(&self.queue, msgid) {
+            sender.send(Err(err.clone()));
         }
-
-        let writer = &mut *self.writer.lock().unwrap();
-        model::encode(writer, req).expect("Error sending message");
+        err
     }
 
-    fn send_msg(&mut self, method: &str, args: Vec<Value>) -> mpsc::Receiver<Result<Value, Value>> {
+    fn send_request(&mut self, method: &str, params: &CallArgs, sender: Option<Sender>) -> u64 {
+        // async calls may wait for burst to end, sync caller is blocked until response
+        let flush = !matches!(sender, Some(Sender::Async(_)));
         let msgid = self.msgid_counter;
         self.msgid_counter += 1;
 
-        let req = model::RpcMessage::RpcRequest {
-            msgid,
-            method: method.to_owned(),
-            params: args,
+        // request is reported by `write_request`, with size of written message
+        let observer = sender.as_ref().and(self.observer.clone());
+        let sender = match (sender, observer.clone()) {
+            (Some(sender), Some(observer)) => {
+                let call = ObservedCall {
+                    observer,
+                    msgid,
+                    method: method.to_owned(),
+                    started: Instant::now(),
+                };
+                Some(Sender::Observed(Box::new(sender), call))
+            }
+            (sender, _) => sender,
         };
 
-        let (sender, receiver) = mpsc::channel();
-        self.queue
-            .lock()
-            .unwrap()
-            .push((msgid, Sender::Sync(sender)));
+        {
+            let mut queue = self.queue.lock().unwrap();
+            let disconnected = self.disconnected.lock().unwrap().clone();
+            match disconnected {
+                None => {
+                    if let Some(sender) = sender {
+                        queue.push((msgid, sender));
+                    }
+                }
+                Some(err) => {
+                    drop(queue);
+                    match sender {
+                        Some(sender) => sender.send(Err(err)),
+                        None => debug!("{}, {} not sent", err, method),
+                    }
+                    return msgid;
+                }
+            }
+        }
 
-        let writer = &mut *self.writer.lock().unwrap();
-        model::encode(writer, req).expect("Error sending message");
+        let res = write_request(
+            &self.writer,
+            &self.tap,
+            observer.as_ref(),
+            msgid,
+            method,
+            params,
+            flush,
+        );
+        if let Err(e) = res {
+            let err = format!("Error sending message: {}", e);
+            error!("{}", err);
+            disconnect(
+                &self.queue,
+                &self.disconnected,
+                CallError::Disconnected(err),
+            );
+        }
 
-        receiver
+        msgid
     }
 
     pub fn call(
@@ -195,102 +657,386 @@ where
         method: &str,
         args: Vec<Value>,
         dur: Option<Duration>,
-    ) -> Result<Value, Value> {
+    ) -> Result<Value, CallError> {
+        self.call_args(method, &CallArgs::from_values(&args), dur)
+    }
+
+    /// Call with borrowed arguments, that are written straight to transport
+    pub fn call_args(
+        &mut self,
+        method: &str,
+        args: &CallArgs,
+        dur: Option<Duration>,
+    ) -> Result<Value, CallError> {
         match dur {
-            Some(dur) => self.call_timeout(method, args, dur),
-            None => self.call_inf(method, args),
+            Some(dur) => self.call_args_timeout(method, args, dur),
+            None => self.call_args_inf(method, args),
         }
     }
 
-    pub fn call_inf(&mut self, method: &str, args: Vec<Value>) -> Result<Value, Value> {
+    pub fn call_inf(&mut self, method: &str, args: Vec<Value>) -> Result<Value, CallError> {
+        self.call_args_inf(method, &CallArgs::from_values(&args))
+    }
+
+    fn call_args_inf(&mut self, method: &str, args: &CallArgs) -> Result<Value, CallError> {
         if !self.event_loop_started {
-            return Err(Value::from("Event loop not started"));
+            return Err(CallError::GenericError("Event loop not started".to_owned()));
+        }
+        if self.is_dispatch_thread() {
+            return self.call_nested(method, args, None);
+        }
+        if self.polled.is_some() {
+            return self.call_polled(method, args, None);
         }
 
-        let receiver = self.send_msg(method, args);
+        let (_, receiver) = self.send_msg(method, args);
 
         receiver.recv().unwrap()
     }
 
-    fn send_error_to_callers(queue: &Queue, err: &Box<Error>) {
-        let mut queue = queue.lock().unwrap();
-        queue.drain(0..).for_each(|sender| {
-            sender
-                .1
-                .send(Err(Value::from(format!("Error read response: {}", err))))
-        });
+    fn is_dispatch_thread(&self) -> bool {
+        self.dispatch_thread == Some(thread::current().id())
     }
 
-    fn dispatch_thread<H>(
+    /// Sync call from handler, that runs on dispatch thread
+    ///
+    /// Nobody else can read the response, so messages are read here.
+    /// Responses are delivered to callers, requests and notifications
+    /// are left for dispatch thread to handle after current handler returns.
+    /// Timeout is checked only between messages.
+    fn call_nested(
+        &mut self,
+        method: &str,
+        args: &CallArgs,
+        dur: Option<Duration>,
+    ) -> Result<Value, CallError> {
+        let instant = Instant::now();
+        let incoming = self.incoming.clone().unwrap();
+
+        let (msgid, receiver) = self.send_msg(method, args);
+
+        loop {
+            match receiver.try_recv() {
+                Err(mpsc::TryRecvError::Empty) => (),
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    return Err(CallError::GenericError(format!(
+                        "Channel disconnected ({})",
+                        method
+                    )))
+                }
+                Ok(val) => return val,
+            };
+
+            let timeout = match dur {
+                Some(dur) => {
+                    let elapsed = instant.elapsed();
+                    if elapsed >= dur {
+                        return Err(self.timed_out(msgid, method));
+                    }
+                    Some(dur - elapsed)
+                }
+                None => None,
+            };
+
+            match incoming.read(timeout) {
+                Ok(None) => (),
+                Ok(Some(model::RpcMessage::RpcResponse {
+                    msgid,
+                    result,
+                    error,
+                })) => deliver_response(&self.queue, msgid, result, error),
+                Ok(Some(msg)) => incoming.backlog.lock().unwrap().push_back(msg),
+                Err(e) => {
+                    let err = read_error(e, &self.disconnect_context);
+                    error!("{}", err);
+                    disconnect(&self.queue, &self.disconnected, err);
+                }
+            }
+        }
+    }
+
+    /// Sync call in poll mode, messages are read on current thread until response arrives
+    fn call_polled(
+        &mut self,
+        method: &str,
+        args: &CallArgs,
+        dur: Option<Duration>,
+    ) -> Result<Value, CallError> {
+        let instant = Instant::now();
+
+        let (msgid, receiver) = self.send_msg(method, args);
+
+        loop {
+            match receiver.try_recv() {
+                Err(mpsc::TryRecvError::Empty) => (),
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    return Err(CallError::GenericError(format!(
+                        "Channel disconnected ({})",
+                        method
+                    )))
+                }
+                Ok(val) => return val,
+            };
+
+            let timeout = match dur {
+                Some(dur) => {
+                    let elapsed = instant.elapsed();
+                    if elapsed >= dur {
+                        return Err(self.timed_out(msgid, method));
+                    }
+                    Some(dur - elapsed)
+                }
+                None => None,
+            };
+
+            if let Err(err) = self.pump(timeout) {
+                return receiver.try_recv().unwrap_or(Err(err));
+            }
+        }
+    }
+
+    fn dispatch_thread<D>(
         queue: Queue,
-        mut reader: BufReader<R>,
-        writer: Arc<Mutex<BufWriter<W>>>,
-        mut handler: H,
+        incoming: Arc<Incoming>,
+        sink: ResponseSink,
+        disconnected: Disconnected,
+        disconnect_context: Option<DisconnectContext>,
+        done: mpsc::Sender<()>,
+        mut dispatcher: D,
     ) -> JoinHandle<()>
     where
-        H: Handler + Send + 'static,
+        D: Dispatcher + Send + 'static,
     {
         thread::spawn(move || loop {
-            let msg = match model::decode(&mut reader) {
+            let msg = match incoming.next() {
                 Ok(msg) => msg,
+                Err(_) if disconnected.lock().unwrap().is_some() => {
+                    debug!("Session disconnected, stop dispatch thread");
+                    drop(done);
+                    return;
+                }
                 Err(e) => {
-                    error!("Error while reading: {}", e);
-                    Self::send_error_to_callers(&queue, &e);
+                    let err = read_error(e, &disconnect_context);
+                    error!("{}", err);
+                    disconnect(&queue, &disconnected, err);
+                    drop(done);
                     return;
                 }
             };
-            debug!("Get message {:?}", msg);
-            match msg {
-                model::RpcMessage::RpcRequest {
-                    msgid,
-                    method,
-                    params,
-                } => {
-                    let response = match handler.handle_request(&method, params) {
-                        Ok(result) => model::RpcMessage::RpcResponse {
-                            msgid,
-                            result,
-                            error: Value::Nil,
-                        },
-                        Err(error) => model::RpcMessage::RpcResponse {
-                            msgid,
-                            result: Value::Nil,
-                            error,
-                        },
-                    };
+            dispatch_msg(&mut dispatcher, &queue, &sink, msg);
+        })
+    }
+}
 
-                    let writer = &mut *writer.lock().unwrap();
-                    model::encode(writer, response).expect("Error sending RPC response");
-                }
-                model::RpcMessage::RpcResponse {
+fn dispatch_msg<D>(dispatcher: &mut D, queue: &Queue, sink: &ResponseSink, msg: model::RpcMessage)
+where
+    D: Dispatcher + ?Sized,
+{
+    debug!("Get message {:?}", msg);
+    match msg {
+        model::RpcMessage::RpcRequest {
+            msgid,
+            method,
+            params,
+        } => {
+            dispatcher.dispatch_request(method, params, Responder::new(msgid, sink.clone()));
+        }
+        model::RpcMessage::RpcResponse {
+            msgid,
+            result,
+            error,
+        } => deliver_response(queue, msgid, result, error),
+        model::RpcMessage::RpcNotification { method, params } => {
+            dispatcher.dispatch_notify(method, params);
+        }
+    };
+}
+
+fn response_sink<W>(
+    queue: &Queue,
+    writer: &Writer<W>,
+    tap: &Option<Tap>,
+    disconnected: &Disconnected,
+) -> ResponseSink
+where
+    W: Write + Send + 'static,
+{
+    let queue = queue.clone();
+    let writer = writer.clone();
+    let tap = tap.clone();
+    let disconnected = disconnected.clone();
+    Arc::new(move |response| {
+        if let Err(e) = write_msg(&writer, &tap, response) {
+            let err = format!("Error sending RPC response: {}", e);
+            error!("{}", err);
+            disconnect(&queue, &disconnected, CallError::Disconnected(err));
+        }
+    })
+}
+
+impl<R, W> Drop for Client<R, W>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Write request with borrowed arguments, closed writer is silently skipped
+///
+/// Observer is told about request with its encoded size before message is flushed,
+/// so it always sees request before response.
+fn write_request<W: Write>(
+    writer: &Writer<W>,
+    tap: &Option<Tap>,
+    observer: Option<&Arc<dyn Observer>>,
+    msgid: u64,
+    method: &str,
+    params: &CallArgs,
+    flush: bool,
+) -> Result<(), Box<dyn Error>> {
+    match *writer.lock().unwrap() {
+        Some(ref mut sink) => {
+            // tapped under writer lock, so request is recorded before its response
+            let tapped = tap.as_ref().map(|tap| {
+                let msg = model::RpcMessage::RpcRequest {
                     msgid,
-                    result,
-                    error,
-                } => {
-                    let sender = find_sender(&queue, msgid);
-                    if error != Value::Nil {
-                        sender.send(Err(error));
-                    } else {
-                        sender.send(Ok(result));
-                    }
+                    method: method.to_owned(),
+                    params: params.to_values(),
+                };
+                tap(Direction::Outgoing, &msg);
+                (tap, msg)
+            });
+            let res = sink.write(flush, |writer| {
+                let mut counter = Counter::new(&mut *writer);
+                model::encode_request(&mut counter, msgid, method, params)?;
+                if let Some(observer) = observer {
+                    observer.on_request(&RequestEvent {
+                        msgid,
+                        method,
+                        size: counter.size,
+                    });
                 }
-                model::RpcMessage::RpcNotification { method, params } => {
-                    handler.handle_notify(&method, params);
-                }
-            };
-        })
+                writer.flush()?;
+                Ok(())
+            });
+            if let (Err(_), Some((tap, msg))) = (&res, tapped) {
+                tap(Direction::Unsent, &msg);
+            }
+            res
+        }
+        None => Ok(()),
     }
 }
 
+/// Write message and flush it right away, closed writer is silently skipped
+fn write_msg<W: Write>(
+    writer: &Writer<W>,
+    tap: &Option<Tap>,
+    msg: model::RpcMessage,
+) -> Result<(), Box<dyn Error>> {
+    match *writer.lock().unwrap() {
+        Some(ref mut sink) => {
+            let tapped = tap.as_ref().map(|tap| {
+                tap(Direction::Outgoing, &msg);
+                (tap, msg.clone())
+            });
+            let res = sink.write(true, |mut writer| model::encode(&mut writer, msg));
+            if let (Err(_), Some((tap, msg))) = (&res, tapped) {
+                tap(Direction::Unsent, &msg);
+            }
+            res
+        }
+        None => Ok(()),
+    }
+}
+
+fn read_error(e: Box<dyn Error>, context: &Option<DisconnectContext>) -> CallError {
+    let mut err = format!("Error read response: {}", e);
+    if let Some(context) = context.as_ref().and_then(|c| c()) {
+        err = format!("{} ({})", err, context);
+    }
+    CallError::Disconnected(err)
+}
+
+fn deliver_response(queue: &Queue, msgid: u64, result: Value, error: Value) {
+    let sender = match find_sender(queue, msgid) {
+        Some(sender) => sender,
+        None => {
+            warn!(
+                "Response to unknown, timed out or cancelled request {}",
+                msgid
+            );
+            return;
+        }
+    };
+    if error != Value::Nil {
+        sender.send(Err(map_generic_error(error)));
+    } else {
+        sender.send(Ok(result));
+    }
+}
+
+/// Mark connection as lost and finish all pending calls with `err`
+///
+/// First error is kept, so later calls are rejected with the original reason.
+fn disconnect(queue: &Queue, disconnected: &Disconnected, err: CallError) {
+    let senders: Vec<_> = {
+        let mut queue = queue.lock().unwrap();
+        let mut disconnected = disconnected.lock().unwrap();
+        if disconnected.is_none() {
+            *disconnected = Some(err.clone());
+        }
+        queue.drain(0..).collect()
+    };
+    senders
+        .into_iter()
+        .for_each(|(_, sender)| sender.send(Err(err.clone())));
+}
+
 /* The idea to use Vec here instead of HashMap
  * is that Vec is faster on small queue sizes
  * in most cases Vec.len = 1 so we just take first item in iteration.
  */
-fn find_sender(queue: &Queue, msgid: u64) -> Sender {
+fn find_sender(queue: &Queue, msgid: u64) -> Option<Sender> {
     let mut queue = queue.lock().unwrap();
 
-    let pos = queue.iter().position(|req| req.0 == msgid).unwrap();
-    queue.remove(pos).1
+    let pos = queue.iter().position(|req| req.0 == msgid)?;
+    Some(queue.remove(pos).1)
+}
+
+/// Handle to cancel async call
+///
+/// Cancelled call callback is never called, response is ignored.
+pub struct CancelHandle {
+    queue: Weak<Mutex<Vec<(u64, Sender)>>>,
+    msgid: Option<u64>,
+}
+
+impl CancelHandle {
+    /// Handle of call, that can't be cancelled
+    pub fn detached() -> CancelHandle {
+        CancelHandle {
+            queue: Weak::new(),
+            msgid: None,
+        }
+    }
+
+    /// Returns `false` if call is already finished
+    pub fn cancel(self) -> bool {
+        match (self.queue.upgrade(), self.msgid) {
+            (Some(queue), Some(msgid)) => match find_sender(&queue, msgid) {
+                Some(sender) => {
+                    sender.cancel();
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -320,5 +1066,47 @@ mod tests {
         assert_eq!(1, queue.lock().unwrap().len());
         find_sender(&queue, 3);
         assert!(queue.lock().unwrap().is_empty());
+        assert!(find_sender(&queue, 3).is_none());
+    }
+
+    #[test]
+    fn test_cancel_observed() {
+        use super::super::observer::Metrics;
+
+        let metrics = Arc::new(Metrics::new());
+        metrics.on_request(&RequestEvent {
+            msgid: 1,
+            method: "nvim_command",
+            size: 10,
+        });
+        let queue = Arc::new(Mutex::new(Vec::new()));
+        let call = ObservedCall {
+            observer: metrics.clone(),
+            msgid: 1,
+            method: "nvim_command".to_owned(),
+            started: Instant::now(),
+        };
+        let cb: Callback = Box::new(|_| panic!("cancelled call callback"));
+        queue
+            .lock()
+            .unwrap()
+            .push((1, Sender::Observed(Box::new(Sender::Async(cb)), call)));
+
+        let handle = CancelHandle {
+            queue: Arc::downgrade(&queue),
+            msgid: Some(1),
+        };
+        assert!(handle.cancel());
+        assert!(queue.lock().unwrap().is_empty());
+
+        // late response is not counted, request is not pending anymore
+        metrics.on_response(&ResponseEvent {
+            msgid: 1,
+            method: "nvim_command",
+            elapsed: Duration::from_millis(1),
+            size: 0,
+            result: Ok(()),
+        });
+        assert_eq!(0, metrics.method("nvim_command").unwrap().request_bytes);
     }
 }
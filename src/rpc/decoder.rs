@@ -0,0 +1,596 @@
+//! Msgpack decoder, that never panics on malformed input
+//!
+//! Sizes, declared in message, are checked against `DecodeLimits`
+//! before anything is allocated, so short header can't make reader
+//! allocate gigabytes.
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+
+use rmp::encode::write_str_len;
+use rmp::Marker;
+use rmpv::decode::read_value as read_rmpv_value;
+use rmpv::Value;
+
+use super::model::RpcMessage;
+
+/// Error in data received from peer
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// Transport error, `UnexpectedEof` kind if connection is closed
+    Io(io::Error),
+    /// Message is larger than given limit
+    TooLarge(usize),
+    /// Message nesting is deeper than given limit
+    TooDeep(usize),
+    /// Reserved msgpack marker
+    InvalidMarker(u8),
+    /// Message is not an array
+    NotArray,
+    /// Wrong number of message fields
+    InvalidLength(usize),
+    /// Unknown message type
+    UnknownType(u64),
+    /// Field has wrong type
+    InvalidField(&'static str),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProtocolError::Io(ref e) => write!(f, "{}", e),
+            ProtocolError::TooLarge(limit) => {
+                write!(f, "Message is larger than {} bytes", limit)
+            }
+            ProtocolError::TooDeep(limit) => {
+                write!(f, "Message is nested deeper than {} levels", limit)
+            }
+            ProtocolError::InvalidMarker(marker) => {
+                write!(f, "Invalid msgpack marker 0x{:x}", marker)
+            }
+            ProtocolError::NotArray => write!(f, "Rpc message must be array"),
+            ProtocolError::InvalidLength(len) => {
+                write!(f, "Wrong number of rpc message fields {}", len)
+            }
+            ProtocolError::UnknownType(msg_type) => {
+                write!(f, "Unknown rpc message type {}", msg_type)
+            }
+            ProtocolError::InvalidField(field) => write!(f, "Invalid rpc message {}", field),
+        }
+    }
+}
+
+impl Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ProtocolError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProtocolError {
+    fn from(err: io::Error) -> Self {
+        ProtocolError::Io(err)
+    }
+}
+
+/// Limits for single incoming message
+#[derive(Debug, Clone)]
+pub struct DecodeLimits {
+    pub(crate) max_size: usize,
+    pub(crate) max_depth: usize,
+}
+
+impl DecodeLimits {
+    pub fn new() -> DecodeLimits {
+        DecodeLimits {
+            max_size: 256 * 1024 * 1024,
+            max_depth: 128,
+        }
+    }
+
+    /// Max encoded message size in bytes, 256 MiB by default
+    pub fn set_max_size(&mut self, max_size: usize) -> &mut Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Max nesting of arrays and maps, 128 by default
+    pub fn set_max_depth(&mut self, max_depth: usize) -> &mut Self {
+        self.max_depth = max_depth;
+        self
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits::new()
+    }
+}
+
+/// Read and validate rpc message
+pub fn read_message<R: Read>(
+    reader: &mut R,
+    limits: &DecodeLimits,
+) -> Result<RpcMessage, ProtocolError> {
+    message_from_value(read_value(reader, limits)?)
+}
+
+/// Read single msgpack value
+pub fn read_value<R: Read>(reader: &mut R, limits: &DecodeLimits) -> Result<Value, ProtocolError> {
+    let mut reader = Limited {
+        reader,
+        remaining: limits.max_size,
+        limits,
+    };
+    reader.value(0)
+}
+
+/// Check message structure and convert it to rpc message
+pub fn message_from_value(val: Value) -> Result<RpcMessage, ProtocolError> {
+    let arr = match val {
+        Value::Array(arr) => arr,
+        _ => return Err(ProtocolError::NotArray),
+    };
+    let msg_type = match arr.first() {
+        Some(msg_type) => msg_type
+            .as_u64()
+            .ok_or(ProtocolError::InvalidField("type"))?,
+        None => return Err(ProtocolError::InvalidLength(0)),
+    };
+    let expected_len = match msg_type {
+        0 | 1 => 4,
+        2 => 3,
+        _ => return Err(ProtocolError::UnknownType(msg_type)),
+    };
+    if arr.len() != expected_len {
+        return Err(ProtocolError::InvalidLength(arr.len()));
+    }
+
+    let mut fields = arr.into_iter().skip(1);
+    let mut next = || fields.next().unwrap();
+    match msg_type {
+        0 => Ok(RpcMessage::RpcRequest {
+            msgid: msgid(next())?,
+            method: method(next())?,
+            params: params(next())?,
+        }),
+        1 => Ok(RpcMessage::RpcResponse {
+            msgid: msgid(next())?,
+            error: next(),
+            result: next(),
+        }),
+        _ => Ok(RpcMessage::RpcNotification {
+            method: method(next())?,
+            params: params(next())?,
+        }),
+    }
+}
+
+fn msgid(val: Value) -> Result<u64, ProtocolError> {
+    val.as_u64().ok_or(ProtocolError::InvalidField("msgid"))
+}
+
+fn method(val: Value) -> Result<String, ProtocolError> {
+    match val {
+        Value::String(s) => s.into_str().ok_or(ProtocolError::InvalidField("method")),
+        _ => Err(ProtocolError::InvalidField("method")),
+    }
+}
+
+fn params(val: Value) -> Result<Vec<Value>, ProtocolError> {
+    match val {
+        Value::Array(params) => Ok(params),
+        _ => Err(ProtocolError::InvalidField("params")),
+    }
+}
+
+/// Reader, that counts every byte of message against size limit
+struct Limited<'a, R: 'a> {
+    reader: &'a mut R,
+    remaining: usize,
+    limits: &'a DecodeLimits,
+}
+
+impl<'a, R: Read> Limited<'a, R> {
+    fn reserve(&mut self, len: usize) -> Result<(), ProtocolError> {
+        if len > self.remaining {
+            return Err(ProtocolError::TooLarge(self.limits.max_size));
+        }
+        self.remaining -= len;
+        Ok(())
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<Vec<u8>, ProtocolError> {
+        self.reserve(len)?;
+        let mut buf = vec![0; len];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Read big endian number of `len` bytes, up to 8
+    fn number(&mut self, len: usize) -> Result<u64, ProtocolError> {
+        self.reserve(len)?;
+        let mut buf = [0; 8];
+        self.reader.read_exact(&mut buf[..len])?;
+        Ok(buf[..len]
+            .iter()
+            .fold(0, |acc, &byte| (acc << 8) | u64::from(byte)))
+    }
+
+    fn u8(&mut self) -> Result<u8, ProtocolError> {
+        Ok(self.number(1)? as u8)
+    }
+
+    fn u16(&mut self) -> Result<u16, ProtocolError> {
+        Ok(self.number(2)? as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32, ProtocolError> {
+        Ok(self.number(4)? as u32)
+    }
+
+    fn u64(&mut self) -> Result<u64, ProtocolError> {
+        self.number(8)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, ProtocolError> {
+        let byte = self.u8()?;
+        let val = match Marker::from_u8(byte) {
+            Marker::FixPos(n) => Value::from(n),
+            Marker::FixNeg(n) => Value::from(n),
+            Marker::Null => Value::Nil,
+            Marker::True => Value::Boolean(true),
+            Marker::False => Value::Boolean(false),
+            Marker::U8 => Value::from(self.u8()?),
+            Marker::U16 => Value::from(self.u16()?),
+            Marker::U32 => Value::from(self.u32()?),
+            Marker::U64 => Value::from(self.u64()?),
+            Marker::I8 => Value::from(self.u8()? as i8),
+            Marker::I16 => Value::from(self.u16()? as i16),
+            Marker::I32 => Value::from(self.u32()? as i32),
+            Marker::I64 => Value::from(self.u64()? as i64),
+            Marker::F32 => Value::F32(f32::from_bits(self.u32()?)),
+            Marker::F64 => Value::F64(f64::from_bits(self.u64()?)),
+            Marker::FixStr(len) => self.string(len as usize)?,
+            Marker::Str8 => {
+                let len = self.u8()? as usize;
+                self.string(len)?
+            }
+            Marker::Str16 => {
+                let len = self.u16()? as usize;
+                self.string(len)?
+            }
+            Marker::Str32 => {
+                let len = self.u32()? as usize;
+                self.string(len)?
+            }
+            Marker::Bin8 => {
+                let len = self.u8()? as usize;
+                Value::Binary(self.bytes(len)?)
+            }
+            Marker::Bin16 => {
+                let len = self.u16()? as usize;
+                Value::Binary(self.bytes(len)?)
+            }
+            Marker::Bin32 => {
+                let len = self.u32()? as usize;
+                Value::Binary(self.bytes(len)?)
+            }
+            Marker::FixArray(len) => self.array_value(len as usize, depth)?,
+            Marker::Array16 => {
+                let len = self.u16()? as usize;
+                self.array_value(len, depth)?
+            }
+            Marker::Array32 => {
+                let len = self.u32()? as usize;
+                self.array_value(len, depth)?
+            }
+            Marker::FixMap(len) => self.map_value(len as usize, depth)?,
+            Marker::Map16 => {
+                let len = self.u16()? as usize;
+                self.map_value(len, depth)?
+            }
+            Marker::Map32 => {
+                let len = self.u32()? as usize;
+                self.map_value(len, depth)?
+            }
+            Marker::FixExt1 => self.ext(1)?,
+            Marker::FixExt2 => self.ext(2)?,
+            Marker::FixExt4 => self.ext(4)?,
+            Marker::FixExt8 => self.ext(8)?,
+            Marker::FixExt16 => self.ext(16)?,
+            Marker::Ext8 => {
+                let len = self.u8()? as usize;
+                self.ext(len)?
+            }
+            Marker::Ext16 => {
+                let len = self.u16()? as usize;
+                self.ext(len)?
+            }
+            Marker::Ext32 => {
+                let len = self.u32()? as usize;
+                self.ext(len)?
+            }
+            Marker::Reserved => return Err(ProtocolError::InvalidMarker(byte)),
+        };
+        Ok(val)
+    }
+
+    fn string(&mut self, len: usize) -> Result<Value, ProtocolError> {
+        let data = self.bytes(len)?;
+        match String::from_utf8(data) {
+            Ok(s) => Ok(Value::from(s)),
+            Err(e) => Ok(invalid_utf8_string(&e.into_bytes())),
+        }
+    }
+
+    fn array_value(&mut self, len: usize, depth: usize) -> Result<Value, ProtocolError> {
+        self.check_nested(len, depth)?;
+        let mut arr = Vec::with_capacity(len);
+        for _ in 0..len {
+            arr.push(self.value(depth + 1)?);
+        }
+        Ok(Value::Array(arr))
+    }
+
+    fn map_value(&mut self, len: usize, depth: usize) -> Result<Value, ProtocolError> {
+        self.check_nested(len.saturating_mul(2), depth)?;
+        let mut map = Vec::with_capacity(len);
+        for _ in 0..len {
+            let key = self.value(depth + 1)?;
+            let val = self.value(depth + 1)?;
+            map.push((key, val));
+        }
+        Ok(Value::Map(map))
+    }
+
+    /// Every item takes at least one byte, so declared length can be checked before allocation
+    fn check_nested(&self, items: usize, depth: usize) -> Result<(), ProtocolError> {
+        if depth >= self.limits.max_depth {
+            return Err(ProtocolError::TooDeep(self.limits.max_depth));
+        }
+        if items > self.remaining {
+            return Err(ProtocolError::TooLarge(self.limits.max_size));
+        }
+        Ok(())
+    }
+
+    fn ext(&mut self, len: usize) -> Result<Value, ProtocolError> {
+        let ext_type = self.u8()? as i8;
+        Ok(Value::Ext(ext_type, self.bytes(len)?))
+    }
+}
+
+/// `Utf8String` keeps invalid data only when created by rmpv decoder
+fn invalid_utf8_string(data: &[u8]) -> Value {
+    let mut buf = Vec::with_capacity(data.len() + 5);
+    write_str_len(&mut buf, data.len() as u32).expect("Can't write to vec");
+    buf.extend_from_slice(data);
+    read_rmpv_value(&mut &buf[..]).expect("Can't decode string")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmpv::encode::write_value;
+    use rpc::model;
+
+    fn decode(data: &[u8], limits: &DecodeLimits) -> Result<RpcMessage, ProtocolError> {
+        read_message(&mut &data[..], limits)
+    }
+
+    fn encode_value(val: &Value) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_value(&mut data, val).unwrap();
+        data
+    }
+
+    fn sample_messages() -> Vec<Vec<u8>> {
+        let messages = vec![
+            RpcMessage::RpcRequest {
+                msgid: 7,
+                method: "nvim_buf_set_lines".to_owned(),
+                params: vec![
+                    Value::Ext(0, vec![1]),
+                    Value::from(-1),
+                    Value::from(vec![Value::from("line"), Value::Binary(vec![0xff, 0])]),
+                ],
+            },
+            RpcMessage::RpcResponse {
+                msgid: u64::MAX,
+                error: Value::from(vec![Value::from(0), Value::from("error")]),
+                result: Value::Map(vec![(Value::from("k"), Value::F64(1.5))]),
+            },
+            RpcMessage::RpcNotification {
+                method: "redraw".to_owned(),
+                params: vec![Value::from(vec![Value::from(i64::MIN), Value::F32(0.5)])],
+            },
+        ];
+
+        messages
+            .into_iter()
+            .map(|msg| {
+                let mut data = Vec::new();
+                model::encode(&mut data, msg).unwrap();
+                data
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_messages() {
+        for data in sample_messages() {
+            let expected = model::decode_value(read_rmpv_value(&mut &data[..]).unwrap()).unwrap();
+            assert_eq!(expected, decode(&data, &DecodeLimits::new()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_invalid_structure() {
+        let cases = vec![
+            (Value::from(1), "Rpc message must be array"),
+            (Value::Array(vec![]), "Wrong number of rpc message fields 0"),
+            (
+                Value::from(vec![Value::from(0)]),
+                "Wrong number of rpc message fields 1",
+            ),
+            (
+                Value::from(vec![Value::from(1), Value::from(2)]),
+                "Wrong number of rpc message fields 2",
+            ),
+            (
+                Value::from(vec![Value::from(5), Value::Nil, Value::Nil]),
+                "Unknown rpc message type 5",
+            ),
+            (
+                Value::from(vec![Value::from("x"), Value::Nil, Value::Nil]),
+                "Invalid rpc message type",
+            ),
+            (
+                Value::from(vec![
+                    Value::from(0),
+                    Value::from(-1),
+                    Value::from("m"),
+                    Value::Array(vec![]),
+                ]),
+                "Invalid rpc message msgid",
+            ),
+            (
+                Value::from(vec![Value::from(2), Value::from("m"), Value::Nil]),
+                "Invalid rpc message params",
+            ),
+        ];
+
+        for (val, err) in cases {
+            let res = decode(&encode_value(&val), &DecodeLimits::new());
+            assert_eq!(err, res.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        // array header, that declares 4G elements
+        let huge = [0x94, 0x02, 0xa1, b'm', 0xdd, 0xff, 0xff, 0xff, 0xff];
+        match decode(&huge, &DecodeLimits::new()) {
+            Err(ProtocolError::TooLarge(_)) => (),
+            res => panic!("Unexpected result {:?}", res),
+        }
+
+        let data = &sample_messages()[0];
+        match decode(data, DecodeLimits::new().set_max_size(data.len() - 1)) {
+            Err(ProtocolError::TooLarge(limit)) => assert_eq!(data.len() - 1, limit),
+            res => panic!("Unexpected result {:?}", res),
+        }
+        assert!(decode(data, DecodeLimits::new().set_max_size(data.len())).is_ok());
+
+        let mut nested = Value::Nil;
+        for _ in 0..10 {
+            nested = Value::from(vec![nested]);
+        }
+        let val = Value::from(vec![Value::from(2), Value::from("m"), nested]);
+        match decode(&encode_value(&val), DecodeLimits::new().set_max_depth(5)) {
+            Err(ProtocolError::TooDeep(5)) => (),
+            res => panic!("Unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_numbers() {
+        let values = vec![
+            Value::from(200u64),
+            Value::from(60_000u64),
+            Value::from(4_000_000_000u64),
+            Value::from(u64::max_value()),
+            Value::from(-100),
+            Value::from(-30_000),
+            Value::from(-2_000_000_000),
+            Value::from(i64::min_value()),
+            Value::F32(1.5),
+            Value::F64(-0.25),
+        ];
+        for val in values {
+            let mut data = Vec::new();
+            rmpv::encode::write_value(&mut data, &val).unwrap();
+            assert_eq!(
+                val,
+                read_value(&mut &data[..], &DecodeLimits::new()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let mut data = Vec::new();
+        write_str_len(&mut data, 2).unwrap();
+        data.extend_from_slice(&[0xff, 0xfe]);
+
+        let val = read_value(&mut &data[..], &DecodeLimits::new()).unwrap();
+        assert_eq!(read_rmpv_value(&mut &data[..]).unwrap(), val);
+        assert_eq!(Some(&[0xff, 0xfe][..]), val.as_slice());
+    }
+
+    /// xorshift, so fuzz cases are reproducible without extra dependencies
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn test_fuzz_random_bytes() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut limits = DecodeLimits::new();
+        limits.set_max_size(4096).set_max_depth(16);
+
+        for _ in 0..20_000 {
+            let len = rng.below(64);
+            let data: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+            // must not panic, hang or allocate more than limit
+            decode(&data, &limits).ok();
+        }
+    }
+
+    #[test]
+    fn test_fuzz_mutations() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let samples = sample_messages();
+        let mut limits = DecodeLimits::new();
+        limits.set_max_size(4096).set_max_depth(16);
+
+        for _ in 0..20_000 {
+            let mut data = samples[rng.below(samples.len())].clone();
+            match rng.below(3) {
+                0 => {
+                    let len = rng.below(data.len());
+                    data.truncate(len);
+                    match decode(&data, &limits) {
+                        Err(ProtocolError::Io(ref e))
+                            if e.kind() == io::ErrorKind::UnexpectedEof => {}
+                        res => panic!("Truncated message decoded as {:?}", res),
+                    }
+                }
+                1 => {
+                    let pos = rng.below(data.len());
+                    data[pos] = rng.next() as u8;
+                    decode(&data, &limits).ok();
+                }
+                _ => {
+                    let pos = rng.below(data.len());
+                    data.insert(pos, rng.next() as u8);
+                    decode(&data, &limits).ok();
+                }
+            }
+        }
+    }
+}
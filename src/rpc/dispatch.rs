@@ -0,0 +1,22 @@
+use rmpv::Value;
+
+use super::handler::{Handler, Responder};
+
+/// Decides where incoming requests and notifications are handled
+pub trait Dispatcher {
+    fn dispatch_request(&mut self, method: String, params: Vec<Value>, responder: Responder);
+    fn dispatch_notify(&mut self, method: String, params: Vec<Value>);
+}
+
+/// Handle everything on dispatch thread
+pub struct Inline<H: Handler>(pub H);
+
+impl<H: Handler> Dispatcher for Inline<H> {
+    fn dispatch_request(&mut self, method: String, params: Vec<Value>, responder: Responder) {
+        self.0.handle_request_deferred(&method, params, responder);
+    }
+
+    fn dispatch_notify(&mut self, method: String, params: Vec<Value>) {
+        self.0.handle_notify(&method, params);
+    }
+}
@@ -1,10 +1,87 @@
 use rmpv::Value;
-use std::sync::mpsc;
+use std::fmt;
+use std::sync::{mpsc, Arc};
+
+use super::model::RpcMessage;
 
 pub trait RequestHandler {
     fn handle_request(&mut self, _name: &str, _args: Vec<Value>) -> Result<Value, Value> {
         Err(Value::from("Not implemented"))
     }
+
+    /// Handle request, that can be answered later from any thread
+    ///
+    /// Default implementation answers with result of `handle_request`.
+    fn handle_request_deferred(&mut self, name: &str, args: Vec<Value>, responder: Responder) {
+        responder.respond(self.handle_request(name, args));
+    }
+}
+
+/// Writes response back to the peer
+pub(crate) type ResponseSink = Arc<dyn Fn(RpcMessage) + Send + Sync>;
+
+/// Response to single incoming request
+///
+/// Can be completed only once. If responder is dropped without answer,
+/// error response is sent, so the peer doesn't wait forever.
+pub struct Responder {
+    msgid: u64,
+    sink: Option<ResponseSink>,
+}
+
+impl Responder {
+    pub(crate) fn new(msgid: u64, sink: ResponseSink) -> Self {
+        Responder {
+            msgid,
+            sink: Some(sink),
+        }
+    }
+
+    /// Id of the request
+    pub fn msgid(&self) -> u64 {
+        self.msgid
+    }
+
+    /// Send response
+    pub fn respond(mut self, res: Result<Value, Value>) {
+        self.send(res);
+    }
+
+    fn send(&mut self, res: Result<Value, Value>) {
+        let sink = match self.sink.take() {
+            Some(sink) => sink,
+            None => return,
+        };
+
+        let response = match res {
+            Ok(result) => RpcMessage::RpcResponse {
+                msgid: self.msgid,
+                result,
+                error: Value::Nil,
+            },
+            Err(error) => RpcMessage::RpcResponse {
+                msgid: self.msgid,
+                result: Value::Nil,
+                error,
+            },
+        };
+        sink(response);
+    }
+}
+
+impl Drop for Responder {
+    fn drop(&mut self) {
+        if self.sink.is_some() {
+            warn!("Request {} is dropped without response", self.msgid);
+            self.send(Err(Value::from("Request dropped without response")));
+        }
+    }
+}
+
+impl fmt::Debug for Responder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Responder {{ msgid: {} }}", self.msgid)
+    }
 }
 
 pub trait Handler: RequestHandler {
@@ -23,7 +100,9 @@ pub struct ChannelHandler<H: RequestHandler> {
 
 impl<H: RequestHandler> Handler for ChannelHandler<H> {
     fn handle_notify(&mut self, name: &str, args: Vec<Value>) {
-        self.sender.send((name.to_owned(), args)).unwrap()
+        if self.sender.send((name.to_owned(), args)).is_err() {
+            debug!("Notification receiver is dropped, {} ignored", name);
+        }
     }
 }
 
@@ -31,6 +110,11 @@ impl<H: RequestHandler> RequestHandler for ChannelHandler<H> {
     fn handle_request(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Value> {
         self.request_handler.handle_request(name, args)
     }
+
+    fn handle_request_deferred(&mut self, name: &str, args: Vec<Value>, responder: Responder) {
+        self.request_handler
+            .handle_request_deferred(name, args, responder)
+    }
 }
 
 impl<H: RequestHandler> ChannelHandler<H> {
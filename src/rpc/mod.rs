@@ -1,9 +1,22 @@
+pub mod args;
+pub mod channel;
 mod client;
+pub mod decoder;
+mod dispatch;
 pub mod handler;
 pub mod model;
+pub mod observer;
+pub mod pipe;
+pub mod poll;
+pub mod pool;
+pub mod record;
+pub mod router;
+pub mod writer;
 
-pub use self::client::Client;
+pub use self::args::{CallArgs, EncodeArg};
+pub use self::client::{CancelHandle, Client};
 pub use self::model::FromVal;
 pub use self::model::IntoVal;
 pub use self::model::RpcMessage;
+pub use self::model::TryFromVal;
 pub use rmpv::Value;
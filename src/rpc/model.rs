@@ -1,10 +1,13 @@
-use rmpv::decode::read_value;
+use rmp;
 use rmpv::encode::write_value;
 use rmpv::Value;
+use std::cmp::Ordering;
 use std::error::Error;
-use std::io;
 use std::io::{Read, Write};
 
+use super::args::CallArgs;
+use super::decoder::{self, DecodeLimits};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum RpcMessage {
     RpcRequest {
@@ -23,36 +26,6 @@ pub enum RpcMessage {
     }, // 2
 }
 
-macro_rules! try_str {
-    ($exp:expr, $msg:expr) => {
-        match $exp {
-            Value::String(val) => match val.into_str() {
-                Some(s) => s,
-                None => return Err(Box::new(io::Error::new(io::ErrorKind::Other, $msg))),
-            },
-            _ => return Err(Box::new(io::Error::new(io::ErrorKind::Other, $msg))),
-        }
-    };
-}
-
-macro_rules! try_int {
-    ($exp:expr, $msg:expr) => {
-        match $exp.as_u64() {
-            Some(val) => val,
-            _ => return Err(Box::new(io::Error::new(io::ErrorKind::Other, $msg))),
-        }
-    };
-}
-
-macro_rules! try_arr {
-    ($exp:expr, $msg:expr) => {
-        match $exp {
-            Value::Array(arr) => arr,
-            _ => return Err(Box::new(io::Error::new(io::ErrorKind::Other, $msg))),
-        }
-    };
-}
-
 macro_rules! rpc_args {
     ($($e:expr), *) => {{
         let mut vec = Vec::new();
@@ -63,72 +36,83 @@ macro_rules! rpc_args {
     }}
 }
 
-pub fn decode<R: Read>(reader: &mut R) -> Result<RpcMessage, Box<Error>> {
-    let mut arr = try_arr!(read_value(reader)?, "Rpc message must be array");
-    match try_int!(arr[0], "Can't find message type") {
-        0 => {
-            arr.truncate(4);
-            let params = try_arr!(arr.pop().unwrap(), "params not found"); // [3]
-            let method = try_str!(arr.pop().unwrap(), "method not found"); // [2]
-            let msgid = try_int!(arr.pop().unwrap(), "msgid not found"); // [1]
-
-            Ok(RpcMessage::RpcRequest {
-                msgid,
-                method,
-                params,
-            })
-        }
-        1 => {
-            arr.truncate(4);
-            let msgid = try_int!(arr[1], "msgid not found");
-            let result = arr.pop().unwrap(); // [3]
-            let error = arr.pop().unwrap(); // [2]
-            Ok(RpcMessage::RpcResponse {
-                msgid,
-                error,
-                result,
-            })
-        }
-        2 => {
-            arr.truncate(3);
-            let params = try_arr!(arr.pop().unwrap(), "params not found"); // [2]
-            let method = try_str!(arr.pop().unwrap(), "method not found"); // [1]
-            Ok(RpcMessage::RpcNotification { method, params })
-        }
-        _ => Err(Box::new(io::Error::new(
-            io::ErrorKind::Other,
-            "Not nown type",
-        ))),
-    }
+/// Read message with default `DecodeLimits`
+pub fn decode<R: Read>(reader: &mut R) -> Result<RpcMessage, Box<dyn Error>> {
+    Ok(decoder::read_message(reader, &DecodeLimits::default())?)
+}
+
+/// Convert already read msgpack value to rpc message
+pub fn decode_value(val: Value) -> Result<RpcMessage, Box<dyn Error>> {
+    Ok(decoder::message_from_value(val)?)
+}
+
+pub fn encode<W: Write>(writer: &mut W, msg: RpcMessage) -> Result<(), Box<dyn Error>> {
+    write_value(writer, &encode_value(msg))?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Write request without building `Value` from arguments
+pub fn encode_request<W: Write>(
+    writer: &mut W,
+    msgid: u64,
+    method: &str,
+    params: &CallArgs,
+) -> Result<(), Box<dyn Error>> {
+    rmp::encode::write_array_len(writer, 4)?;
+    rmp::encode::write_uint(writer, 0)?;
+    rmp::encode::write_uint(writer, msgid)?;
+    rmp::encode::write_str(writer, method)?;
+    params.write_to(writer)?;
+    writer.flush()?;
+
+    Ok(())
 }
 
-pub fn encode<W: Write>(writer: &mut W, msg: RpcMessage) -> Result<(), Box<Error>> {
+/// Convert rpc message to msgpack value, that is sent over the wire
+pub fn encode_value(msg: RpcMessage) -> Value {
     match msg {
         RpcMessage::RpcRequest {
             msgid,
             method,
             params,
-        } => {
-            let val = rpc_args!(0, msgid, method, params);
-            write_value(writer, &val)?;
-        }
+        } => rpc_args!(0, msgid, method, params),
         RpcMessage::RpcResponse {
             msgid,
             error,
             result,
-        } => {
-            let val = rpc_args!(1, msgid, error, result);
-            write_value(writer, &val)?;
-        }
-        RpcMessage::RpcNotification { method, params } => {
-            let val = rpc_args!(2, method, params);
-            write_value(writer, &val)?;
-        }
-    };
+        } => rpc_args!(1, msgid, error, result),
+        RpcMessage::RpcNotification { method, params } => rpc_args!(2, method, params),
+    }
+}
 
-    writer.flush()?;
+/// Decode ext value, that represent Buffer, Window or Tabpage,
+/// into ext type id and numeric handle
+pub fn decode_ext_handle(val: &Value) -> Option<(i8, i64)> {
+    match *val {
+        Value::Ext(ext_id, ref data) => rmp::decode::read_int(&mut &data[..])
+            .ok()
+            .map(|handle| (ext_id, handle)),
+        _ => None,
+    }
+}
 
-    Ok(())
+/// Encode numeric handle into ext value with given ext type id
+pub fn encode_ext_handle(ext_id: i8, handle: i64) -> Value {
+    let mut data = Vec::new();
+    rmp::encode::write_sint(&mut data, handle).expect("Error encoding handle");
+    Value::Ext(ext_id, data)
+}
+
+/// Order of values, that can't be decoded by `decode_ext_handle`, by their encoding
+pub fn cmp_undecoded(a: &Value, b: &Value) -> Ordering {
+    let encode = |val: &Value| {
+        let mut data = Vec::new();
+        write_value(&mut data, val).expect("Error encoding value");
+        data
+    };
+    encode(a).cmp(&encode(b))
 }
 
 pub trait FromVal<T> {
@@ -190,18 +174,146 @@ impl FromVal<Value> for bool {
     }
 }
 
+/// Panics on invalid utf-8, use `TryFromVal` or `Vec<u8>` for text from buffers
 impl FromVal<Value> for String {
     fn from_val(val: Value) -> Self {
         val.as_str().expect("Can't convert to string").to_owned()
     }
 }
 
+/// Convert bytes to string, invalid utf-8 is replaced with `U+FFFD`
+pub(crate) fn utf8_lossy(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+/// Raw bytes of string or binary value
+impl FromVal<Value> for Vec<u8> {
+    fn from_val(val: Value) -> Self {
+        match val {
+            Value::String(s) => s.into_bytes(),
+            Value::Binary(data) => data,
+            _ => panic!("Can't convert to bytes"),
+        }
+    }
+}
+
 impl FromVal<Value> for i64 {
     fn from_val(val: Value) -> Self {
         val.as_i64().expect("Can't convert to i64")
     }
 }
 
+/// Fallible conversion, used to decode arguments of incoming messages
+pub trait TryFromVal<T>: Sized {
+    fn try_from_val(val: T) -> Result<Self, String>;
+}
+
+fn unexpected<T>(expected: &str, val: &Value) -> Result<T, String> {
+    Err(format!("expected {}, got {}", expected, val))
+}
+
+impl TryFromVal<Value> for Value {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        Ok(val)
+    }
+}
+
+impl TryFromVal<Value> for () {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        match val {
+            Value::Nil => Ok(()),
+            val => unexpected("nil", &val),
+        }
+    }
+}
+
+impl TryFromVal<Value> for bool {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        match val {
+            Value::Boolean(res) => Ok(res),
+            val => unexpected("boolean", &val),
+        }
+    }
+}
+
+impl TryFromVal<Value> for i64 {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        match val.as_i64() {
+            Some(res) => Ok(res),
+            None => unexpected("i64", &val),
+        }
+    }
+}
+
+impl TryFromVal<Value> for u64 {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        match val.as_u64() {
+            Some(res) => Ok(res),
+            None => unexpected("u64", &val),
+        }
+    }
+}
+
+impl TryFromVal<Value> for f64 {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        match val.as_f64() {
+            Some(res) => Ok(res),
+            None => unexpected("f64", &val),
+        }
+    }
+}
+
+impl TryFromVal<Value> for String {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        match val {
+            Value::String(s) => s
+                .into_str()
+                .ok_or_else(|| "expected string, got invalid utf-8".to_owned()),
+            Value::Binary(data) => {
+                String::from_utf8(data).map_err(|_| "expected string, got invalid utf-8".to_owned())
+            }
+            val => unexpected("string", &val),
+        }
+    }
+}
+
+impl TryFromVal<Value> for Vec<u8> {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        match val {
+            Value::String(s) => Ok(s.into_bytes()),
+            Value::Binary(data) => Ok(data),
+            val => unexpected("string", &val),
+        }
+    }
+}
+
+impl<T: TryFromVal<Value>> TryFromVal<Value> for Option<T> {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        match val {
+            Value::Nil => Ok(None),
+            val => T::try_from_val(val).map(Some),
+        }
+    }
+}
+
+impl TryFromVal<Value> for Vec<(Value, Value)> {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        match val {
+            Value::Map(map) => Ok(map),
+            val => unexpected("map", &val),
+        }
+    }
+}
+
+impl<T: TryFromVal<Value>> TryFromVal<Value> for Vec<T> {
+    fn try_from_val(val: Value) -> Result<Self, String> {
+        match val {
+            Value::Array(arr) => arr.into_iter().map(T::try_from_val).collect(),
+            val => unexpected("array", &val),
+        }
+    }
+}
+
 pub trait IntoVal<T> {
     fn into_val(self) -> T;
 }
@@ -281,4 +393,38 @@ mod test {
         let msg_dest = decode(&mut buff).unwrap();
         assert_eq!(msg, msg_dest);
     }
+
+    #[test]
+    fn invalid_utf8_test() {
+        let mut data = vec![0xa3];
+        data.extend_from_slice(b"a\xffb");
+        let val = decoder::read_value(&mut &data[..], &DecodeLimits::default()).unwrap();
+
+        assert_eq!(b"a\xffb".to_vec(), Vec::<u8>::from_val(val.clone()));
+        assert!(String::try_from_val(val.clone()).is_err());
+        assert_eq!(Ok(b"a\xffb".to_vec()), Vec::<u8>::try_from_val(val));
+        assert_eq!(
+            Ok("ab".to_owned()),
+            String::try_from_val(Value::Binary(b"ab".to_vec()))
+        );
+        assert!(Vec::<u8>::try_from_val(Value::from(1)).is_err());
+
+        let lines = Value::from(vec![Value::from("x"), Value::Binary(vec![0xff])]);
+        assert_eq!(
+            vec![b"x".to_vec(), vec![0xff]],
+            Vec::<Vec<u8>>::from_val(lines)
+        );
+    }
+
+    #[test]
+    fn ext_handle_test() {
+        let val = encode_ext_handle(1, 1000);
+        assert_eq!(Some((1, 1000)), decode_ext_handle(&val));
+
+        // nvim sends handles as positive fixint
+        let val = Value::Ext(0, vec![0x05]);
+        assert_eq!(Some((0, 5)), decode_ext_handle(&val));
+
+        assert_eq!(None, decode_ext_handle(&Value::from(5)));
+    }
 }
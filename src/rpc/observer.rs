@@ -0,0 +1,347 @@
+//! Hooks to observe rpc calls, with per-method metrics and `tracing` integration
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use neovim::CallError;
+
+/// Request sent to neovim
+#[derive(Debug)]
+pub struct RequestEvent<'a> {
+    pub msgid: u64,
+    pub method: &'a str,
+    /// Encoded message size in bytes
+    pub size: usize,
+}
+
+/// Call finished with response, timeout or disconnect
+#[derive(Debug)]
+pub struct ResponseEvent<'a> {
+    pub msgid: u64,
+    pub method: &'a str,
+    /// Time since request was sent
+    pub elapsed: Duration,
+    /// Encoded result size in bytes, zero for failed call
+    pub size: usize,
+    pub result: Result<(), &'a CallError>,
+}
+
+/// Async call cancelled before response
+#[derive(Debug)]
+pub struct CancelEvent<'a> {
+    pub msgid: u64,
+    pub method: &'a str,
+    /// Time since request was sent
+    pub elapsed: Duration,
+}
+
+/// Observer of calls, made by client
+///
+/// Called on thread, that sends request, receives response or cancels call,
+/// so implementation must be fast. Every request is finished by either
+/// `on_response` or `on_cancel`.
+pub trait Observer: Send + Sync {
+    fn on_request(&self, _event: &RequestEvent) {}
+
+    fn on_response(&self, _event: &ResponseEvent) {}
+
+    fn on_cancel(&self, _event: &CancelEvent) {}
+}
+
+/// Writer, that counts bytes written to inner writer
+///
+/// Flush is not passed to inner writer, caller flushes it, when message is complete.
+pub(crate) struct Counter<W> {
+    inner: W,
+    pub size: usize,
+}
+
+impl<W: Write> Counter<W> {
+    pub fn new(inner: W) -> Self {
+        Counter { inner, size: 0 }
+    }
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.size += len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Upper bounds of latency histogram buckets, last bucket is unbounded
+pub const LATENCY_BUCKETS: [Duration; 8] = [
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+];
+
+/// Statistics of one method
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MethodStats {
+    pub calls: u64,
+    pub errors: u64,
+    pub total_time: Duration,
+    pub max_time: Duration,
+    /// Calls per `LATENCY_BUCKETS` bucket, plus one for slower calls
+    pub latency: [u64; 9],
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+impl MethodStats {
+    pub fn mean_time(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::new(0, 0)
+        } else {
+            let nanos = self.total_time.as_nanos() / u128::from(self.calls);
+            Duration::new(
+                (nanos / 1_000_000_000) as u64,
+                (nanos % 1_000_000_000) as u32,
+            )
+        }
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.calls as f64
+        }
+    }
+
+    /// Upper bound of bucket, that contains given percentile, `None` for slowest bucket
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let rank = (self.calls as f64 * percentile / 100.0).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, count) in self.latency.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return LATENCY_BUCKETS.get(i).cloned();
+            }
+        }
+        None
+    }
+
+    fn add(&mut self, event: &ResponseEvent) {
+        self.calls += 1;
+        if event.result.is_err() {
+            self.errors += 1;
+        }
+        self.total_time += event.elapsed;
+        self.max_time = self.max_time.max(event.elapsed);
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| event.elapsed <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.latency[bucket] += 1;
+        self.response_bytes += event.size as u64;
+    }
+}
+
+/// Observer, that collects `MethodStats` per method
+///
+/// Stats of call are counted when call finishes, cancelled calls are not counted.
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+#[derive(Default)]
+struct MetricsState {
+    methods: HashMap<String, MethodStats>,
+    /// Request size of calls in flight
+    pending: HashMap<u64, usize>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Stats of all called methods, sorted by method name
+    pub fn snapshot(&self) -> Vec<(String, MethodStats)> {
+        let state = self.state.lock().unwrap();
+        let mut stats: Vec<_> = state
+            .methods
+            .iter()
+            .map(|(method, stats)| (method.clone(), stats.clone()))
+            .collect();
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        stats
+    }
+
+    pub fn method(&self, method: &str) -> Option<MethodStats> {
+        self.state.lock().unwrap().methods.get(method).cloned()
+    }
+
+    /// Forget collected stats
+    pub fn reset(&self) {
+        self.state.lock().unwrap().methods.clear();
+    }
+}
+
+impl Observer for Metrics {
+    fn on_request(&self, event: &RequestEvent) {
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .insert(event.msgid, event.size);
+    }
+
+    fn on_response(&self, event: &ResponseEvent) {
+        let mut state = self.state.lock().unwrap();
+        let request_size = state.pending.remove(&event.msgid).unwrap_or(0);
+        let stats = state.methods.entry(event.method.to_owned()).or_default();
+        stats.add(event);
+        stats.request_bytes += request_size as u64;
+    }
+
+    fn on_cancel(&self, event: &CancelEvent) {
+        self.state.lock().unwrap().pending.remove(&event.msgid);
+    }
+}
+
+/// Observer, that reports every call as `tracing` span
+///
+/// Span `rpc_call` lives from request to response, result is logged as event inside it.
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+pub struct TracingObserver {
+    spans: Mutex<HashMap<u64, ::tracing::Span>>,
+}
+
+#[cfg(feature = "tracing")]
+impl TracingObserver {
+    pub fn new() -> TracingObserver {
+        TracingObserver::default()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Observer for TracingObserver {
+    fn on_request(&self, event: &RequestEvent) {
+        let span = ::tracing::debug_span!(
+            "rpc_call",
+            method = event.method,
+            msgid = event.msgid,
+            request_size = event.size
+        );
+        self.spans.lock().unwrap().insert(event.msgid, span);
+    }
+
+    fn on_response(&self, event: &ResponseEvent) {
+        let span = match self.spans.lock().unwrap().remove(&event.msgid) {
+            Some(span) => span,
+            None => return,
+        };
+        let _enter = span.enter();
+        let elapsed_us = event.elapsed.as_micros() as u64;
+        match event.result {
+            Ok(()) => ::tracing::debug!(elapsed_us, response_size = event.size, "response"),
+            Err(err) => ::tracing::warn!(elapsed_us, error = %err, "call failed"),
+        }
+    }
+
+    fn on_cancel(&self, event: &CancelEvent) {
+        if let Some(span) = self.spans.lock().unwrap().remove(&event.msgid) {
+            let _enter = span.enter();
+            let elapsed_us = event.elapsed.as_micros() as u64;
+            ::tracing::debug!(elapsed_us, "cancelled");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response<'a>(
+        msgid: u64,
+        elapsed: Duration,
+        result: Result<(), &'a CallError>,
+    ) -> ResponseEvent<'a> {
+        ResponseEvent {
+            msgid,
+            method: "nvim_command",
+            elapsed,
+            size: 10,
+            result,
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let metrics = Metrics::new();
+        let err = CallError::GenericError("timeout".to_owned());
+
+        for msgid in 0..4 {
+            metrics.on_request(&RequestEvent {
+                msgid,
+                method: "nvim_command",
+                size: 20,
+            });
+        }
+        metrics.on_response(&response(0, Duration::from_micros(50), Ok(())));
+        metrics.on_response(&response(1, Duration::from_millis(3), Ok(())));
+        metrics.on_response(&response(2, Duration::from_millis(4), Ok(())));
+        metrics.on_response(&response(3, Duration::from_secs(2), Err(&err)));
+
+        let stats = metrics.method("nvim_command").unwrap();
+        assert_eq!(4, stats.calls);
+        assert_eq!(1, stats.errors);
+        assert_eq!(0.25, stats.error_rate());
+        assert_eq!([1, 0, 2, 0, 0, 0, 0, 0, 1], stats.latency);
+        assert_eq!(Duration::from_secs(2), stats.max_time);
+        assert_eq!(80, stats.request_bytes);
+        assert_eq!(40, stats.response_bytes);
+        assert_eq!(Some(Duration::from_millis(5)), stats.percentile(50.0));
+        assert_eq!(None, stats.percentile(99.0));
+
+        assert_eq!(1, metrics.snapshot().len());
+        assert!(metrics.state.lock().unwrap().pending.is_empty());
+        metrics.reset();
+        assert!(metrics.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_metrics_cancel() {
+        let metrics = Metrics::new();
+        metrics.on_request(&RequestEvent {
+            msgid: 1,
+            method: "nvim_command",
+            size: 20,
+        });
+        metrics.on_cancel(&CancelEvent {
+            msgid: 1,
+            method: "nvim_command",
+            elapsed: Duration::from_millis(1),
+        });
+
+        assert!(metrics.state.lock().unwrap().pending.is_empty());
+        assert!(metrics.method("nvim_command").is_none());
+    }
+
+    #[test]
+    fn test_mean_time() {
+        let stats = MethodStats {
+            calls: (1 << 32) + 1,
+            total_time: Duration::from_secs((1 << 32) + 1),
+            ..MethodStats::default()
+        };
+        assert_eq!(Duration::from_secs(1), stats.mean_time());
+        assert_eq!(Duration::new(0, 0), MethodStats::default().mean_time());
+    }
+}
@@ -0,0 +1,81 @@
+//! In-memory byte pipe, transport for sessions without real connection
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Create connected pair, data written to `PipeWriter` is read from `PipeReader`
+pub fn pipe() -> (PipeWriter, PipeReader) {
+    let (sender, receiver) = mpsc::channel();
+    (
+        PipeWriter { sender },
+        PipeReader {
+            receiver,
+            buffer: Vec::new(),
+            pos: 0,
+            timeout: None,
+        },
+    )
+}
+
+/// Writing half of pipe, fails with `BrokenPipe` when reader is dropped
+pub struct PipeWriter {
+    sender: mpsc::Sender<Vec<u8>>,
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.sender
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "Pipe reader is closed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reading half of pipe, reads end of file when writer is dropped
+pub struct PipeReader {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    buffer: Vec<u8>,
+    pos: usize,
+    timeout: Option<Duration>,
+}
+
+impl PipeReader {
+    /// Fail read with `TimedOut` error if no data arrives in time, `None` waits forever
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buffer.len() {
+            let data = match self.timeout {
+                Some(timeout) => match self.receiver.recv_timeout(timeout) {
+                    Ok(data) => data,
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        return Err(io::Error::new(ErrorKind::TimedOut, "Pipe read timed out"))
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(0),
+                },
+                None => match self.receiver.recv() {
+                    Ok(data) => data,
+                    Err(_) => return Ok(0),
+                },
+            };
+            self.buffer = data;
+            self.pos = 0;
+        }
+
+        let n = buf.len().min(self.buffer.len() - self.pos);
+        buf[..n].copy_from_slice(&self.buffer[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
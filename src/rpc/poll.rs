@@ -0,0 +1,352 @@
+//! Read messages on caller thread, without dispatch thread
+use std::error::Error;
+use std::io::{self, Cursor, ErrorKind, Read};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+use rmp::Marker;
+
+use super::decoder::{self, DecodeLimits, ProtocolError};
+use super::model::RpcMessage;
+
+/// Wait until transport has data to read, `None` waits without timeout
+pub type ReadyCheck = Box<dyn FnMut(Option<Duration>) -> io::Result<bool> + Send>;
+
+/// Reader, that never blocks longer than requested
+///
+/// Data is read only when `ready` reports it is available,
+/// incomplete messages are kept in buffer until rest of data arrives.
+pub struct PollReader<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    /// Progress of incomplete message at the start of buffer
+    scan: Scan,
+    /// Error found after some messages of the same read were decoded
+    error: Option<ProtocolError>,
+    ready: ReadyCheck,
+    limits: DecodeLimits,
+}
+
+impl<R: Read> PollReader<R> {
+    pub fn new(reader: R, ready: ReadyCheck) -> Self {
+        PollReader {
+            reader,
+            buffer: Vec::new(),
+            scan: Scan::default(),
+            error: None,
+            ready,
+            limits: DecodeLimits::default(),
+        }
+    }
+
+    pub fn set_decode_limits(&mut self, limits: DecodeLimits) {
+        self.limits = limits;
+    }
+
+    /// Read available data, waiting for it up to `timeout`, and decode complete messages
+    ///
+    /// Returns error when connection is closed and no messages left.
+    /// Invalid data is reported by the next call, after messages decoded before it.
+    pub fn read(&mut self, timeout: Option<Duration>) -> Result<Vec<RpcMessage>, Box<dyn Error>> {
+        if let Some(e) = self.error.take() {
+            return Err(e.into());
+        }
+
+        let mut timeout = timeout;
+        let mut chunk = [0u8; 8192];
+        let mut eof = false;
+
+        while (self.ready)(timeout)? {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => {
+                    eof = true;
+                    break;
+                }
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+            timeout = Some(Duration::new(0, 0));
+        }
+
+        let msgs = self.decode_buffered();
+        if msgs.is_empty() {
+            if let Some(e) = self.error.take() {
+                return Err(e.into());
+            }
+        }
+        if eof && msgs.is_empty() {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "Connection closed").into());
+        }
+        Ok(msgs)
+    }
+
+    /// Decode complete messages, first error is kept in `error`
+    fn decode_buffered(&mut self) -> Vec<RpcMessage> {
+        let mut msgs = Vec::new();
+        let mut consumed = 0;
+
+        while consumed < self.buffer.len() {
+            let end = match self.scan.complete(&self.buffer[consumed..], &self.limits) {
+                Some(end) => end,
+                None => break,
+            };
+
+            let mut cursor = Cursor::new(&self.buffer[consumed..consumed + end]);
+            let res = decoder::read_value(&mut cursor, &self.limits);
+            self.scan = Scan::default();
+            consumed += cursor.position() as usize;
+            match res.and_then(decoder::message_from_value) {
+                Ok(msg) => msgs.push(msg),
+                Err(e) => {
+                    self.error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        self.buffer.drain(..consumed);
+        msgs
+    }
+}
+
+/// Finds end of msgpack value without decoding it
+///
+/// State is kept between calls, so each byte of incomplete message is looked at once.
+#[derive(Default)]
+struct Scan {
+    /// Offset of next header, or of value end when `open` is empty
+    pos: usize,
+    /// Items left in each unfinished array or map
+    open: Vec<u64>,
+    done: bool,
+}
+
+impl Scan {
+    /// Length of value at the start of `buf`, `None` if more data is needed
+    ///
+    /// Value, that breaks `limits`, is cut where it's detected, so decoder reports the error.
+    fn complete(&mut self, buf: &[u8], limits: &DecodeLimits) -> Option<usize> {
+        while !self.done {
+            let marker = *buf.get(self.pos)?;
+            let (header, len) = match Marker::from_u8(marker) {
+                Marker::FixArray(n) => (1, Item::Items(u64::from(n))),
+                Marker::FixMap(n) => (1, Item::Items(2 * u64::from(n))),
+                Marker::FixStr(n) => (1, Item::Body(u64::from(n))),
+                Marker::Array16 => (3, Item::Items(be(buf, self.pos, 2)?)),
+                Marker::Array32 => (5, Item::Items(be(buf, self.pos, 4)?)),
+                Marker::Map16 => (3, Item::Items(2 * be(buf, self.pos, 2)?)),
+                Marker::Map32 => (5, Item::Items(2 * be(buf, self.pos, 4)?)),
+                Marker::Str8 | Marker::Bin8 => (2, Item::Body(be(buf, self.pos, 1)?)),
+                Marker::Str16 | Marker::Bin16 => (3, Item::Body(be(buf, self.pos, 2)?)),
+                Marker::Str32 | Marker::Bin32 => (5, Item::Body(be(buf, self.pos, 4)?)),
+                Marker::Ext8 => (3, Item::Body(be(buf, self.pos, 1)?)),
+                Marker::Ext16 => (4, Item::Body(be(buf, self.pos, 2)?)),
+                Marker::Ext32 => (6, Item::Body(be(buf, self.pos, 4)?)),
+                Marker::FixExt1 => (2, Item::Body(1)),
+                Marker::FixExt2 => (2, Item::Body(2)),
+                Marker::FixExt4 => (2, Item::Body(4)),
+                Marker::FixExt8 => (2, Item::Body(8)),
+                Marker::FixExt16 => (2, Item::Body(16)),
+                Marker::U8 | Marker::I8 => (2, Item::Body(0)),
+                Marker::U16 | Marker::I16 => (3, Item::Body(0)),
+                Marker::U32 | Marker::I32 | Marker::F32 => (5, Item::Body(0)),
+                Marker::U64 | Marker::I64 | Marker::F64 => (9, Item::Body(0)),
+                Marker::Reserved => return Some(self.pos + 1),
+                _ => (1, Item::Body(0)),
+            };
+            if buf.len() < self.pos + header {
+                return None;
+            }
+            self.pos += header;
+
+            let mut finished = match len {
+                Item::Items(items) => {
+                    if self.open.len() >= limits.max_depth {
+                        return Some(self.pos);
+                    }
+                    if items > 0 {
+                        self.open.push(items);
+                    }
+                    items == 0
+                }
+                Item::Body(len) => {
+                    self.pos = self.pos.saturating_add(len as usize);
+                    true
+                }
+            };
+            if self.pos > limits.max_size {
+                return Some(self.pos.min(buf.len()));
+            }
+
+            // finished item may complete its parents
+            while finished {
+                match self.open.last_mut() {
+                    Some(items) => {
+                        *items -= 1;
+                        finished = *items == 0;
+                    }
+                    None => {
+                        self.done = true;
+                        break;
+                    }
+                }
+                if finished {
+                    self.open.pop();
+                }
+            }
+        }
+
+        if buf.len() >= self.pos {
+            Some(self.pos)
+        } else {
+            None
+        }
+    }
+}
+
+enum Item {
+    /// Array or map with given number of values
+    Items(u64),
+    /// Scalar with given number of bytes after header
+    Body(u64),
+}
+
+/// Big endian length, that follows marker at `pos`
+fn be(buf: &[u8], pos: usize, len: usize) -> Option<u64> {
+    let bytes = buf.get(pos + 1..pos + 1 + len)?;
+    Some(bytes.iter().fold(0, |acc, &b| (acc << 8) | u64::from(b)))
+}
+
+/// Check readiness of file descriptor with `poll`
+#[cfg(unix)]
+pub fn fd_ready(fd: RawFd) -> ReadyCheck {
+    use libc;
+
+    Box::new(move |timeout| {
+        let timeout = match timeout {
+            // round up, so short timeout doesn't turn into busy loop
+            Some(timeout) => {
+                ((timeout.as_nanos() + 999_999) / 1_000_000).min(i32::MAX as u128) as i32
+            }
+            None => -1,
+        };
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        loop {
+            let res = unsafe { libc::poll(&mut pollfd, 1, timeout) };
+            if res >= 0 {
+                return Ok(res > 0);
+            }
+
+            let err = io::Error::last_os_error();
+            if err.kind() != ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmpv::Value;
+    use rpc::model;
+
+    #[test]
+    fn test_partial_message() {
+        let mut data = Vec::new();
+        for msgid in 1..3 {
+            model::encode(
+                &mut data,
+                RpcMessage::RpcResponse {
+                    msgid,
+                    error: Value::Nil,
+                    result: Value::from("result"),
+                },
+            )
+            .unwrap();
+        }
+        let tail = data.split_off(data.len() - 3);
+        let mut reader = PollReader::new(Cursor::new(data), Box::new(|_| Ok(true)));
+
+        let msgs = reader.read(Some(Duration::new(0, 0))).unwrap();
+        assert_eq!(1, msgs.len());
+        assert!(!reader.buffer.is_empty());
+
+        reader.reader.get_mut().extend_from_slice(&tail);
+        let msgs = reader.read(Some(Duration::new(0, 0))).unwrap();
+        assert_eq!(1, msgs.len());
+        assert!(reader.buffer.is_empty());
+
+        assert!(reader.read(Some(Duration::new(0, 0))).is_err());
+    }
+
+    #[test]
+    fn test_scan() {
+        let values = vec![
+            Value::from(1),
+            Value::from(-1000),
+            Value::F64(1.5),
+            Value::from("x".repeat(300)),
+            Value::Binary(vec![0; 70000]),
+            Value::Ext(1, vec![1, 2]),
+            Value::Ext(2, vec![0; 3]),
+            Value::from(Vec::<Value>::new()),
+            Value::from(vec![
+                Value::from(vec![Value::Nil, Value::from(Vec::<Value>::new())]),
+                Value::Map(vec![(Value::from("k"), Value::from(vec![Value::from(1)]))]),
+                Value::from(20),
+            ]),
+        ];
+        let limits = DecodeLimits::default();
+
+        for val in values {
+            let mut data = Vec::new();
+            rmpv::encode::write_value(&mut data, &val).unwrap();
+            data.push(0xc0);
+            let len = data.len() - 1;
+
+            let mut scan = Scan::default();
+            for end in 0..len {
+                assert_eq!(None, scan.complete(&data[..end], &limits), "{}", val);
+            }
+            assert_eq!(Some(len), scan.complete(&data, &limits), "{}", val);
+        }
+    }
+
+    #[test]
+    fn test_error_after_messages() {
+        let mut data = Vec::new();
+        model::encode(
+            &mut data,
+            RpcMessage::RpcNotification {
+                method: "redraw".to_owned(),
+                params: vec![],
+            },
+        )
+        .unwrap();
+        // valid msgpack, but not a message
+        data.push(0x01);
+        let mut reader = PollReader::new(Cursor::new(data), Box::new(|_| Ok(true)));
+
+        assert_eq!(1, reader.read(Some(Duration::new(0, 0))).unwrap().len());
+        assert!(reader.read(Some(Duration::new(0, 0))).is_err());
+    }
+
+    #[test]
+    fn test_too_large_partial() {
+        let mut data = vec![0xdb, 0xff, 0xff, 0xff, 0xff];
+        data.extend_from_slice(b"abc");
+        let mut reader = PollReader::new(Cursor::new(data), Box::new(|_| Ok(true)));
+        reader.set_decode_limits(DecodeLimits::new().set_max_size(1024).clone());
+
+        assert!(reader.read(Some(Duration::new(0, 0))).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Handle incoming requests on worker threads
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use rmpv::Value;
+
+use super::dispatch::Dispatcher;
+use super::handler::{Handler, Responder};
+
+/// Where notifications are handled when requests go to worker pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyOrder {
+    /// On dispatch thread, in order of arrival.
+    /// Slow notification handler delays responses.
+    Inline,
+    /// On separate thread, in order of arrival
+    Sequential,
+    /// On worker pool together with requests, order is not preserved
+    Unordered,
+}
+
+/// Options for `Session::start_event_loop_pool`
+///
+/// Every worker, and the notification thread, gets own clone of handler,
+/// so state kept in handler fields is not shared between them.
+/// Keep shared state behind `Arc<Mutex<_>>` in the handler.
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    workers: usize,
+    notify_order: NotifyOrder,
+}
+
+impl PoolOptions {
+    pub fn new() -> PoolOptions {
+        PoolOptions {
+            workers: 4,
+            notify_order: NotifyOrder::Inline,
+        }
+    }
+
+    /// Number of worker threads, 4 by default
+    pub fn set_workers(&mut self, workers: usize) -> &mut Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Notification ordering, `NotifyOrder::Inline` by default
+    pub fn set_notify_order(&mut self, notify_order: NotifyOrder) -> &mut Self {
+        self.notify_order = notify_order;
+        self
+    }
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        PoolOptions::new()
+    }
+}
+
+type Job<H> = Box<dyn FnOnce(&mut H) + Send>;
+
+/// Threads with own handler copy, that take jobs from shared queue
+///
+/// Threads exit when pool is dropped and queue is empty.
+struct WorkerPool<H> {
+    sender: mpsc::Sender<Job<H>>,
+}
+
+impl<H: Handler + Clone + Send + 'static> WorkerPool<H> {
+    fn new(name: &str, handler: &H, workers: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job<H>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..workers {
+            let mut handler = handler.clone();
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("{}-{}", name, i))
+                .spawn(move || loop {
+                    let job = match receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+                    job(&mut handler);
+                })
+                .expect("Can't spawn worker thread");
+        }
+
+        WorkerPool { sender }
+    }
+
+    fn execute<F>(&self, job: F)
+    where
+        F: FnOnce(&mut H) + Send + 'static,
+    {
+        if self.sender.send(Box::new(job)).is_err() {
+            error!("All worker threads are finished, job is dropped");
+        }
+    }
+}
+
+enum NotifyDispatch<H> {
+    Inline(H),
+    Sequential(WorkerPool<H>),
+    Unordered,
+}
+
+/// Run requests on worker pool
+pub struct Pool<H> {
+    requests: WorkerPool<H>,
+    notify: NotifyDispatch<H>,
+}
+
+impl<H: Handler + Clone + Send + 'static> Pool<H> {
+    pub fn new(handler: H, options: &PoolOptions) -> Self {
+        let requests = WorkerPool::new("nvim-worker", &handler, options.workers);
+        let notify = match options.notify_order {
+            NotifyOrder::Inline => NotifyDispatch::Inline(handler),
+            NotifyOrder::Sequential => {
+                NotifyDispatch::Sequential(WorkerPool::new("nvim-notify", &handler, 1))
+            }
+            NotifyOrder::Unordered => NotifyDispatch::Unordered,
+        };
+        Pool { requests, notify }
+    }
+}
+
+impl<H: Handler + Clone + Send + 'static> Dispatcher for Pool<H> {
+    fn dispatch_request(&mut self, method: String, params: Vec<Value>, responder: Responder) {
+        self.requests
+            .execute(move |handler| handler.handle_request_deferred(&method, params, responder));
+    }
+
+    fn dispatch_notify(&mut self, method: String, params: Vec<Value>) {
+        let job = move |handler: &mut H| handler.handle_notify(&method, params);
+        match self.notify {
+            NotifyDispatch::Inline(ref mut handler) => job(handler),
+            NotifyDispatch::Sequential(ref pool) => pool.execute(job),
+            NotifyDispatch::Unordered => self.requests.execute(job),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::handler::{RequestHandler, ResponseSink};
+    use super::super::model::RpcMessage;
+    use super::*;
+    use std::time::Duration;
+
+    #[derive(Clone)]
+    struct TestHandler {
+        notified: mpsc::Sender<u64>,
+        release: Arc<Mutex<mpsc::Receiver<()>>>,
+    }
+
+    impl RequestHandler for TestHandler {
+        fn handle_request(&mut self, name: &str, _args: Vec<Value>) -> Result<Value, Value> {
+            if name == "slow" {
+                let release = self.release.lock().unwrap();
+                release.recv_timeout(Duration::from_secs(5)).unwrap();
+            }
+            Ok(Value::from(name))
+        }
+    }
+
+    impl Handler for TestHandler {
+        fn handle_notify(&mut self, _name: &str, args: Vec<Value>) {
+            let n = args[0].as_u64().unwrap();
+            // uneven handling time, reordered if notifications run in parallel
+            if n % 3 == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            self.notified.send(n).unwrap();
+        }
+    }
+
+    fn pool(options: &PoolOptions) -> (Pool<TestHandler>, mpsc::Receiver<u64>, mpsc::Sender<()>) {
+        let (notified, notifications) = mpsc::channel();
+        let (release, receiver) = mpsc::channel();
+        let handler = TestHandler {
+            notified,
+            release: Arc::new(Mutex::new(receiver)),
+        };
+        (Pool::new(handler, options), notifications, release)
+    }
+
+    #[test]
+    fn test_notify_order() {
+        for &order in &[NotifyOrder::Inline, NotifyOrder::Sequential] {
+            let (mut pool, notifications, _release) =
+                pool(PoolOptions::new().set_notify_order(order));
+            for n in 0..50 {
+                pool.dispatch_notify("event".to_owned(), vec![Value::from(n)]);
+            }
+
+            let received: Vec<u64> = (0..50)
+                .map(|_| notifications.recv_timeout(Duration::from_secs(5)).unwrap())
+                .collect();
+            assert_eq!((0..50).collect::<Vec<u64>>(), received, "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn test_slow_request() {
+        let (mut pool, _notifications, release) = pool(PoolOptions::new().set_workers(2));
+        let (sender, responses) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let sink: ResponseSink = Arc::new(move |msg| sender.lock().unwrap().send(msg).unwrap());
+
+        for &(msgid, method) in &[(1, "slow"), (2, "fast")] {
+            let responder = Responder::new(msgid, sink.clone());
+            pool.dispatch_request(method.to_owned(), vec![], responder);
+        }
+
+        let response = |msgid: u64, method: &str| RpcMessage::RpcResponse {
+            msgid,
+            error: Value::Nil,
+            result: Value::from(method),
+        };
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            response(2, "fast"),
+            responses.recv_timeout(timeout).unwrap()
+        );
+        release.send(()).unwrap();
+        assert_eq!(
+            response(1, "slow"),
+            responses.recv_timeout(timeout).unwrap()
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Record rpc traffic to reproduce it later with `replay::Player`
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rmpv::decode::read_value;
+use rmpv::encode::write_value;
+use rmpv::Value;
+
+use super::model::{self, RpcMessage};
+
+/// Function, that sees every message read from or written to transport
+pub type Tap = Arc<dyn Fn(Direction, &RpcMessage) + Send + Sync>;
+
+/// Message direction, from the point of view of client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Message sent by neovim
+    Incoming,
+    /// Message sent by client
+    Outgoing,
+    /// Message recorded as `Outgoing`, that failed to write
+    Unsent,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Incoming => "in",
+            Direction::Outgoing => "out",
+            Direction::Unsent => "unsent",
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.as_str())
+    }
+}
+
+/// Recorded message
+///
+/// Stored as msgpack array `[direction, microseconds since start, message]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub direction: Direction,
+    pub time: Duration,
+    pub message: RpcMessage,
+}
+
+impl Record {
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let micros = self.time.as_micros().min(u64::MAX as u128) as u64;
+        let val = Value::from(vec![
+            Value::from(self.direction.as_str()),
+            Value::from(micros),
+            model::encode_value(self.message.clone()),
+        ]);
+        write_value(writer, &val)?;
+        Ok(())
+    }
+
+    /// Read next record, `None` at the end of stream
+    ///
+    /// Stream, that ends in the middle of record, is an error.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Option<Record>, Box<dyn Error>> {
+        let mut first = [0; 1];
+        loop {
+            match reader.read(&mut first) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let val = match read_value(&mut (&first[..]).chain(reader)) {
+            Ok(val) => val,
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => {
+                return Err("Truncated record".into())
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut fields = match val {
+            Value::Array(fields) if fields.len() == 3 => fields.into_iter(),
+            val => return Err(format!("Invalid record {}", val).into()),
+        };
+        let direction = match fields.next().unwrap().as_str() {
+            Some("in") => Direction::Incoming,
+            Some("out") => Direction::Outgoing,
+            Some("unsent") => Direction::Unsent,
+            _ => return Err("Invalid record direction".into()),
+        };
+        let time = match fields.next().unwrap().as_u64() {
+            Some(micros) => Duration::from_micros(micros),
+            None => return Err("Invalid record time".into()),
+        };
+        let message = model::decode_value(fields.next().unwrap())?;
+
+        Ok(Some(Record {
+            direction,
+            time,
+            message,
+        }))
+    }
+}
+
+/// Read all records from file written by `Recorder`
+pub fn read_records<P: AsRef<Path>>(path: P) -> Result<Vec<Record>, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    while let Some(record) = Record::read(&mut reader)? {
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Writes every message with direction and timestamp
+///
+/// Each record is flushed immediately, so trace is complete even if plugin crashes.
+pub struct Recorder {
+    writer: Mutex<Box<dyn Write + Send>>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Recorder {
+        Recorder {
+            writer: Mutex::new(Box::new(writer)),
+            start: Instant::now(),
+        }
+    }
+
+    /// Record to new file, existing file is truncated
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Recorder> {
+        Ok(Recorder::new(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn record(&self, direction: Direction, message: &RpcMessage) {
+        let record = Record {
+            direction,
+            time: self.start.elapsed(),
+            message: message.clone(),
+        };
+
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(e) = record.write(&mut *writer).and_then(|_| Ok(writer.flush()?)) {
+            warn!("Can't record message: {}", e);
+        }
+    }
+
+    /// Convert to tap for `Client::set_tap`
+    pub fn into_tap(self) -> Tap {
+        Arc::new(move |direction, message| self.record(direction, message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_record_roundtrip() {
+        let records = vec![
+            Record {
+                direction: Direction::Outgoing,
+                time: Duration::from_micros(10),
+                message: RpcMessage::RpcRequest {
+                    msgid: 0,
+                    method: "nvim_get_current_line".to_owned(),
+                    params: vec![],
+                },
+            },
+            Record {
+                direction: Direction::Incoming,
+                time: Duration::from_micros(250),
+                message: RpcMessage::RpcResponse {
+                    msgid: 0,
+                    error: Value::Nil,
+                    result: Value::from("line"),
+                },
+            },
+            Record {
+                direction: Direction::Unsent,
+                time: Duration::from_micros(300),
+                message: RpcMessage::RpcNotification {
+                    method: "nvim_command".to_owned(),
+                    params: vec![],
+                },
+            },
+        ];
+
+        let mut data = Vec::new();
+        for record in &records {
+            record.write(&mut data).unwrap();
+        }
+
+        let mut reader = Cursor::new(data);
+        let mut read = Vec::new();
+        while let Some(record) = Record::read(&mut reader).unwrap() {
+            read.push(record);
+        }
+        assert_eq!(records, read);
+
+        let mut invalid = Cursor::new(Vec::new());
+        write_value(&mut invalid, &Value::from(vec![Value::from("up")])).unwrap();
+        invalid.set_position(0);
+        assert!(Record::read(&mut invalid).is_err());
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        let mut data = Vec::new();
+        Record {
+            direction: Direction::Incoming,
+            time: Duration::from_micros(10),
+            message: RpcMessage::RpcNotification {
+                method: "redraw".to_owned(),
+                params: vec![],
+            },
+        }
+        .write(&mut data)
+        .unwrap();
+        let len = data.len();
+        data.truncate(len - 1);
+
+        let err = Record::read(&mut Cursor::new(data)).unwrap_err();
+        assert_eq!("Truncated record", err.to_string());
+        assert!(Record::read(&mut Cursor::new(Vec::new()))
+            .unwrap()
+            .is_none());
+    }
+}
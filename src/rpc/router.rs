@@ -0,0 +1,244 @@
+//! Route incoming messages to closures by method name
+//!
+//! ```no_run
+//! use neovim_lib::{Router, Session, Value};
+//!
+//! let mut router = Router::new();
+//! router
+//!     .notify("buf_changed", |(buf, tick): (i64, i64)| {
+//!         println!("buffer {} changed, tick {}", buf, tick);
+//!     })
+//!     .request("upper", |(text,): (String,)| Ok(text.to_uppercase()))
+//!     .fallback_request(|name, _args| Err(Value::from(format!("No such method {}", name))));
+//!
+//! let mut session = Session::new_parent().unwrap();
+//! session.start_event_loop_handler(router);
+//! ```
+use std::collections::HashMap;
+
+use rmpv::Value;
+
+use super::handler::{Handler, RequestHandler};
+use super::model::{IntoVal, TryFromVal};
+
+/// Decode message arguments into rust type
+///
+/// Implemented for tuples of `TryFromVal` types, that expect exact number of arguments,
+/// and for `Vec<Value>` to get arguments as is.
+pub trait FromArgs: Sized {
+    fn from_args(args: Vec<Value>) -> Result<Self, String>;
+}
+
+impl FromArgs for Vec<Value> {
+    fn from_args(args: Vec<Value>) -> Result<Self, String> {
+        Ok(args)
+    }
+}
+
+impl FromArgs for () {
+    fn from_args(args: Vec<Value>) -> Result<Self, String> {
+        if args.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("expected no arguments, got {}", args.len()))
+        }
+    }
+}
+
+macro_rules! tuple_from_args {
+    ($len:expr; $($name:ident)+) => {
+        impl<$($name: TryFromVal<Value>),+> FromArgs for ($($name,)+) {
+            fn from_args(args: Vec<Value>) -> Result<Self, String> {
+                if args.len() != $len {
+                    return Err(format!("expected {} arguments, got {}", $len, args.len()));
+                }
+
+                let mut args = args.into_iter().enumerate();
+                Ok(($({
+                    let (idx, arg) = args.next().unwrap();
+                    $name::try_from_val(arg).map_err(|e| format!("argument {}: {}", idx, e))?
+                },)+))
+            }
+        }
+    };
+}
+
+tuple_from_args!(1; A);
+tuple_from_args!(2; A B);
+tuple_from_args!(3; A B C);
+tuple_from_args!(4; A B C D);
+tuple_from_args!(5; A B C D E);
+tuple_from_args!(6; A B C D E F);
+
+type NotifyRoute = Box<dyn FnMut(Vec<Value>) -> Result<(), String> + Send>;
+type RequestRoute = Box<dyn FnMut(Vec<Value>) -> Result<Value, Value> + Send>;
+type NotifyFallback = Box<dyn FnMut(&str, Vec<Value>) + Send>;
+type RequestFallback = Box<dyn FnMut(&str, Vec<Value>) -> Result<Value, Value> + Send>;
+
+/// `Handler` that calls closure registered for method name
+///
+/// Arguments are decoded before closure is called. If decoding fails,
+/// request is answered with error and notification is logged.
+/// Unknown methods go to fallback, by default request is answered
+/// with error and notification is ignored.
+#[derive(Default)]
+pub struct Router {
+    notify: HashMap<String, NotifyRoute>,
+    request: HashMap<String, RequestRoute>,
+    notify_fallback: Option<NotifyFallback>,
+    request_fallback: Option<RequestFallback>,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// Call `f` for notification `name`
+    pub fn notify<A, F>(&mut self, name: &str, mut f: F) -> &mut Self
+    where
+        A: FromArgs,
+        F: FnMut(A) + Send + 'static,
+    {
+        self.notify.insert(
+            name.to_owned(),
+            Box::new(move |args| A::from_args(args).map(&mut f)),
+        );
+        self
+    }
+
+    /// Call `f` for request `name`, returned value is sent as response
+    pub fn request<A, T, F>(&mut self, name: &str, mut f: F) -> &mut Self
+    where
+        A: FromArgs,
+        T: IntoVal<Value>,
+        F: FnMut(A) -> Result<T, Value> + Send + 'static,
+    {
+        let method = name.to_owned();
+        self.request.insert(
+            name.to_owned(),
+            Box::new(move |args| match A::from_args(args) {
+                Ok(args) => f(args).map(IntoVal::into_val),
+                Err(e) => Err(Value::from(format!(
+                    "Invalid arguments for {}: {}",
+                    method, e
+                ))),
+            }),
+        );
+        self
+    }
+
+    /// Handle notifications without registered route
+    pub fn fallback_notify<F>(&mut self, f: F) -> &mut Self
+    where
+        F: FnMut(&str, Vec<Value>) + Send + 'static,
+    {
+        self.notify_fallback = Some(Box::new(f));
+        self
+    }
+
+    /// Handle requests without registered route
+    pub fn fallback_request<F>(&mut self, f: F) -> &mut Self
+    where
+        F: FnMut(&str, Vec<Value>) -> Result<Value, Value> + Send + 'static,
+    {
+        self.request_fallback = Some(Box::new(f));
+        self
+    }
+}
+
+impl RequestHandler for Router {
+    fn handle_request(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Value> {
+        if let Some(route) = self.request.get_mut(name) {
+            return route(args);
+        }
+
+        match self.request_fallback {
+            Some(ref mut fallback) => fallback(name, args),
+            None => Err(Value::from(format!("Unknown method {}", name))),
+        }
+    }
+}
+
+impl Handler for Router {
+    fn handle_notify(&mut self, name: &str, args: Vec<Value>) {
+        if let Some(route) = self.notify.get_mut(name) {
+            if let Err(e) = route(args) {
+                error!("Invalid arguments for {}: {}", name, e);
+            }
+            return;
+        }
+
+        match self.notify_fallback {
+            Some(ref mut fallback) => fallback(name, args),
+            None => debug!("Unhandled notification {}", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_from_args() {
+        assert_eq!(
+            Ok((1, "a".to_owned(), None)),
+            <(i64, String, Option<bool>)>::from_args(vec![
+                Value::from(1),
+                Value::from("a"),
+                Value::Nil,
+            ])
+        );
+        assert_eq!(
+            Err("argument 1: expected string, got 2".to_owned()),
+            <(i64, String)>::from_args(vec![Value::from(1), Value::from(2)])
+        );
+        assert_eq!(
+            Err("expected 2 arguments, got 1".to_owned()),
+            <(i64, i64)>::from_args(vec![Value::from(1)])
+        );
+    }
+
+    #[test]
+    fn test_router() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let notify_events = events.clone();
+        let fallback_events = events.clone();
+
+        let mut router = Router::new();
+        router
+            .notify("changed", move |(tick,): (i64,)| {
+                notify_events
+                    .lock()
+                    .unwrap()
+                    .push(format!("changed {}", tick))
+            })
+            .request("add", |(a, b): (i64, i64)| Ok(a + b))
+            .fallback_notify(move |name, _| fallback_events.lock().unwrap().push(name.to_owned()));
+
+        router.handle_notify("changed", vec![Value::from(3)]);
+        router.handle_notify("changed", vec![Value::from("bad")]);
+        router.handle_notify("other", vec![]);
+        assert_eq!(
+            vec!["changed 3".to_owned(), "other".to_owned()],
+            *events.lock().unwrap()
+        );
+
+        assert_eq!(
+            Ok(Value::from(3)),
+            router.handle_request("add", vec![Value::from(1), Value::from(2)])
+        );
+        assert_eq!(
+            Err(Value::from(
+                "Invalid arguments for add: expected 2 arguments, got 0"
+            )),
+            router.handle_request("add", vec![])
+        );
+        assert_eq!(
+            Err(Value::from("Unknown method sub")),
+            router.handle_request("sub", vec![])
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Outgoing side of connection, written directly or by writer thread
+use std::error::Error;
+use std::io::{self, BufWriter, Write};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Options for `Session::start_writer_thread`
+#[derive(Debug, Clone)]
+pub struct WriterOptions {
+    max_batch: usize,
+    linger: Duration,
+}
+
+impl WriterOptions {
+    pub fn new() -> WriterOptions {
+        WriterOptions {
+            max_batch: 64 * 1024,
+            linger: Duration::new(0, 0),
+        }
+    }
+
+    /// Bytes written between flushes during a burst, 64 KiB by default
+    pub fn set_max_batch(&mut self, max_batch: usize) -> &mut Self {
+        self.max_batch = max_batch.max(1);
+        self
+    }
+
+    /// Time to wait more messages before flush, zero by default
+    ///
+    /// Messages, that need response right away, like sync calls, are never delayed.
+    pub fn set_linger(&mut self, linger: Duration) -> &mut Self {
+        self.linger = linger;
+        self
+    }
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        WriterOptions::new()
+    }
+}
+
+/// Encoded message for writer thread
+pub(crate) struct Frame {
+    pub data: Vec<u8>,
+    /// Flush right after this message, without waiting for burst to end
+    pub flush: bool,
+}
+
+/// Where messages are written
+pub(crate) enum Sink<W: Write> {
+    /// Message is written and flushed by caller
+    Direct(BufWriter<W>),
+    /// Message is queued to writer thread
+    Queued(mpsc::Sender<Frame>),
+}
+
+impl<W: Write> Sink<W> {
+    /// Write message, that is encoded by `encode`
+    ///
+    /// Direct sink is flushed by `encode`, queued message is flushed by writer thread.
+    pub fn write<F>(&mut self, flush: bool, encode: F) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<(), Box<dyn Error>>,
+    {
+        match *self {
+            Sink::Direct(ref mut writer) => encode(writer),
+            Sink::Queued(ref sender) => {
+                let mut data = Vec::new();
+                encode(&mut data)?;
+                sender
+                    .send(Frame { data, flush })
+                    .map_err(|_| "Writer thread stopped".into())
+            }
+        }
+    }
+}
+
+/// Running writer thread
+pub(crate) struct WriterThread {
+    guard: JoinHandle<()>,
+    done: mpsc::Receiver<()>,
+}
+
+impl WriterThread {
+    /// Wait thread to write queued messages, thread is detached after `timeout`
+    ///
+    /// Sender must be dropped before.
+    pub fn join(self, timeout: Duration) {
+        match self.done.recv_timeout(timeout) {
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                warn!("Writer thread is still running, detach it");
+            }
+            _ => {
+                self.guard.join().ok();
+            }
+        }
+    }
+}
+
+/// Start thread, that writes frames to `writer` and coalesces bursts
+///
+/// `on_error` is called once, when write fails, after that thread stops.
+pub(crate) fn spawn<W, F>(
+    writer: BufWriter<W>,
+    options: &WriterOptions,
+    on_error: F,
+) -> io::Result<(mpsc::Sender<Frame>, WriterThread)>
+where
+    W: Write + Send + 'static,
+    F: FnOnce(io::Error) + Send + 'static,
+{
+    let writer = BufWriter::with_capacity(options.max_batch, writer.into_inner()?);
+    let (sender, receiver) = mpsc::channel();
+    let (done_sender, done) = mpsc::channel();
+    let options = options.clone();
+
+    let guard = thread::Builder::new()
+        .name("nvim-writer".to_owned())
+        .spawn(move || {
+            if let Err(e) = write_loop(writer, &receiver, &options) {
+                on_error(e);
+            }
+            done_sender.send(()).ok();
+        })?;
+
+    Ok((sender, WriterThread { guard, done }))
+}
+
+fn write_loop<W: Write>(
+    mut writer: BufWriter<W>,
+    receiver: &mpsc::Receiver<Frame>,
+    options: &WriterOptions,
+) -> io::Result<()> {
+    while let Ok(frame) = receiver.recv() {
+        let deadline = Instant::now() + options.linger;
+        let mut flush = frame.flush;
+        let mut batch = frame.data.len();
+        writer.write_all(&frame.data)?;
+
+        while !flush && batch < options.max_batch {
+            let next = match deadline.checked_duration_since(Instant::now()) {
+                Some(wait) if wait > Duration::new(0, 0) => receiver.recv_timeout(wait).ok(),
+                _ => receiver.try_recv().ok(),
+            };
+            match next {
+                Some(frame) => {
+                    flush = frame.flush;
+                    batch += frame.data.len();
+                    writer.write_all(&frame.data)?;
+                }
+                None => break,
+            }
+        }
+
+        writer.flush()?;
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Writer, that records every write and flush
+    #[derive(Clone, Default)]
+    struct Log {
+        writes: Arc<Mutex<Vec<Vec<u8>>>>,
+        flushes: Arc<Mutex<usize>>,
+    }
+
+    impl Write for Log {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes.lock().unwrap().push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            *self.flushes.lock().unwrap() += 1;
+            Ok(())
+        }
+    }
+
+    fn frame(data: &[u8], flush: bool) -> Frame {
+        Frame {
+            data: data.to_vec(),
+            flush,
+        }
+    }
+
+    #[test]
+    fn test_burst_is_coalesced() {
+        let log = Log::default();
+        let mut options = WriterOptions::new();
+        options.set_linger(Duration::from_millis(200));
+        let (sender, thread) = spawn(BufWriter::new(log.clone()), &options, |_| ()).unwrap();
+
+        for i in 0..100u8 {
+            sender.send(frame(&[i], false)).unwrap();
+        }
+        drop(sender);
+        thread.join(Duration::new(1, 0));
+
+        let writes = log.writes.lock().unwrap();
+        assert_eq!(1, writes.len());
+        assert_eq!((0..100).collect::<Vec<u8>>(), writes[0]);
+    }
+
+    #[test]
+    fn test_flush_is_not_delayed() {
+        let log = Log::default();
+        let mut options = WriterOptions::new();
+        options.set_linger(Duration::new(10, 0));
+        let (sender, thread) = spawn(BufWriter::new(log.clone()), &options, |_| ()).unwrap();
+
+        sender.send(frame(b"async", false)).unwrap();
+        sender.send(frame(b"sync", true)).unwrap();
+
+        let start = Instant::now();
+        while log.writes.lock().unwrap().is_empty() {
+            assert!(
+                start.elapsed() < Duration::new(5, 0),
+                "Sync frame is delayed"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(vec![b"asyncsync".to_vec()], *log.writes.lock().unwrap());
+
+        drop(sender);
+        thread.join(Duration::new(1, 0));
+    }
+
+    #[test]
+    fn test_max_batch() {
+        let log = Log::default();
+        let mut options = WriterOptions::new();
+        options
+            .set_max_batch(4)
+            .set_linger(Duration::from_millis(200));
+        let (sender, thread) = spawn(BufWriter::new(log.clone()), &options, |_| ()).unwrap();
+
+        for _ in 0..4 {
+            sender.send(frame(b"ab", false)).unwrap();
+        }
+        drop(sender);
+        thread.join(Duration::new(1, 0));
+
+        assert_eq!(
+            vec![b"abab".to_vec(), b"abab".to_vec()],
+            *log.writes.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_error_stops_thread() {
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let (err_sender, err_receiver) = mpsc::channel();
+        let (sender, thread) = spawn(BufWriter::new(Broken), &WriterOptions::new(), move |e| {
+            err_sender.send(e.kind()).unwrap();
+        })
+        .unwrap();
+
+        sender.send(frame(b"msg", true)).unwrap();
+        assert_eq!(
+            io::ErrorKind::BrokenPipe,
+            err_receiver.recv_timeout(Duration::new(1, 0)).unwrap()
+        );
+        thread.join(Duration::new(1, 0));
+        assert!(sender.send(frame(b"msg", true)).is_err());
+    }
+}
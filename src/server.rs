@@ -0,0 +1,158 @@
+//! Accept connections from neovim instances
+//!
+//! Neovim can connect to a running process with `sockconnect()`,
+//! every accepted connection becomes separate `Session`.
+//!
+//! ```no_run
+//! use neovim_lib::{Listener, Neovim, NeovimApi};
+//!
+//! let listener = Listener::bind_tcp("127.0.0.1:6666").unwrap();
+//! for session in listener.incoming() {
+//!     let mut session = session.unwrap();
+//!     session.start_event_loop();
+//!     let mut nvim = Neovim::new(session);
+//!     nvim.command("echo \"Connected\"").unwrap();
+//! }
+//! ```
+use std::io::Result;
+use std::net::{TcpListener, ToSocketAddrs};
+
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(unix)]
+use unix_socket::UnixListener;
+
+use session::Session;
+
+/// Listener for incoming neovim connections.
+pub struct Listener {
+    listener: ListenerKind,
+}
+
+enum ListenerKind {
+    Tcp(TcpListener),
+
+    #[cfg(unix)]
+    UnixSocket(UnixListener),
+}
+
+impl Listener {
+    /// Listen for connections on tcp address
+    pub fn bind_tcp<A: ToSocketAddrs>(addr: A) -> Result<Listener> {
+        Ok(Self::from_tcp_listener(TcpListener::bind(addr)?))
+    }
+
+    /// Use already bound tcp listener
+    pub fn from_tcp_listener(listener: TcpListener) -> Listener {
+        Listener {
+            listener: ListenerKind::Tcp(listener),
+        }
+    }
+
+    #[cfg(unix)]
+    /// Listen for connections on unix socket
+    pub fn bind_unix_socket<P: AsRef<Path>>(path: P) -> Result<Listener> {
+        Ok(Self::from_unix_listener(UnixListener::bind(path)?))
+    }
+
+    #[cfg(unix)]
+    /// Use already bound unix socket listener
+    pub fn from_unix_listener(listener: UnixListener) -> Listener {
+        Listener {
+            listener: ListenerKind::UnixSocket(listener),
+        }
+    }
+
+    /// Wait for new connection
+    ///
+    /// Returned session is not started, so every connection
+    /// can start event loop with own handler.
+    pub fn accept(&self) -> Result<Session> {
+        match self.listener {
+            ListenerKind::Tcp(ref listener) => {
+                let (stream, _) = listener.accept()?;
+                Session::from_tcp_stream(stream)
+            }
+
+            #[cfg(unix)]
+            ListenerKind::UnixSocket(ref listener) => {
+                let (stream, _) = listener.accept()?;
+                Session::from_unix_stream(stream)
+            }
+        }
+    }
+
+    /// Iterator over incoming connections, never returns `None`
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+}
+
+/// Iterator over sessions, created by `Listener::incoming`
+pub struct Incoming<'a> {
+    listener: &'a Listener,
+}
+
+impl<'a> Iterator for Incoming<'a> {
+    type Item = Result<Session>;
+
+    fn next(&mut self) -> Option<Result<Session>> {
+        Some(self.listener.accept())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmpv::Value;
+    use rpc::handler::{Handler, RequestHandler};
+    use rpc::model::{self, RpcMessage};
+    use std::net::TcpStream;
+    use std::result;
+
+    struct EchoHandler;
+
+    impl RequestHandler for EchoHandler {
+        fn handle_request(
+            &mut self,
+            name: &str,
+            _args: Vec<Value>,
+        ) -> result::Result<Value, Value> {
+            Ok(Value::from(name))
+        }
+    }
+
+    impl Handler for EchoHandler {}
+
+    #[test]
+    fn test_accept_tcp() {
+        let listener = Listener::from_tcp_listener(TcpListener::bind("127.0.0.1:0").unwrap());
+        let addr = match listener.listener {
+            ListenerKind::Tcp(ref listener) => listener.local_addr().unwrap(),
+            #[cfg(unix)]
+            _ => unreachable!(),
+        };
+
+        let mut nvim = TcpStream::connect(addr).unwrap();
+        let mut session = listener.incoming().next().unwrap().unwrap();
+        session.start_event_loop_handler(EchoHandler);
+
+        model::encode(
+            &mut nvim,
+            RpcMessage::RpcRequest {
+                msgid: 1,
+                method: "ping".to_owned(),
+                params: vec![],
+            },
+        ).unwrap();
+
+        assert_eq!(
+            RpcMessage::RpcResponse {
+                msgid: 1,
+                error: Value::Nil,
+                result: Value::from("ping"),
+            },
+            model::decode(&mut nvim).unwrap()
+        );
+    }
+}
@@ -1,29 +1,85 @@
 use std::io::Result;
-use std::io::{Error, ErrorKind, Stdin, Stdout};
-use std::net::TcpStream;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::process::Stdio;
-use std::process::{Child, ChildStdin, ChildStdout, Command};
+use std::process::{Child, ChildStderr, Command, ExitStatus};
 use std::result;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use std::path::Path;
+use tempdir::TempDir;
 #[cfg(unix)]
 use unix_socket::UnixStream;
 
 use rpc;
+use rpc::channel::{ChannelOptions, NotifyReceiver};
+use rpc::decoder::DecodeLimits;
 use rpc::handler::{DefaultHandler, Handler, RequestHandler};
-use rpc::Client;
+use rpc::model::IntoVal;
+#[cfg(unix)]
+use rpc::poll;
+use rpc::pool::PoolOptions;
+use rpc::observer::Observer;
+use rpc::record::Recorder;
+use rpc::writer::WriterOptions;
+use rpc::{CallArgs, CancelHandle, Client};
 
 use async::AsyncCall;
+use neovim::{AsyncCallback, CallBackend, CallError};
 
 use rmpv::Value;
 
 /// An active Neovim session.
 pub struct Session {
     client: ClientConnection,
-    timeout: Option<Duration>,
+    child: Option<Arc<Mutex<Child>>>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) tmp_dir: Option<TempDir>,
+    #[cfg(unix)]
+    raw_fd: Option<RawFd>,
+}
+
+impl CallBackend for Session {
+    fn call(&mut self, method: &str, args: Vec<Value>) -> result::Result<Value, CallError> {
+        Session::call(self, method, args)
+    }
+
+    fn call_args(&mut self, method: &str, args: &CallArgs) -> result::Result<Value, CallError> {
+        Session::call_args(self, method, args)
+    }
+
+    fn call_async(
+        &mut self,
+        method: &str,
+        args: Vec<Value>,
+        cb: Option<AsyncCallback>,
+    ) -> CancelHandle {
+        self.client.call_async(method.to_owned(), args, cb)
+    }
+}
+
+/// Boxed reader half of session transport
+pub type BoxedReader = Box<dyn Read + Send>;
+/// Boxed writer half of session transport
+pub type BoxedWriter = Box<dyn Write + Send>;
+
+/// Rpc client used by `Session`, concrete transport is hidden behind boxed reader and writer
+pub type ClientConnection = Client<BoxedReader, BoxedWriter>;
+
+/// Destination for stderr output of child neovim process
+#[derive(Clone)]
+pub enum StderrMode {
+    /// Child process inherits stderr of current process
+    Inherit,
+    /// Every line is forwarded to `log` crate with warning level
+    Log,
+    /// Every line is passed to callback
+    Callback(Arc<dyn Fn(&str) + Send + Sync>),
 }
 
 macro_rules! call_args {
@@ -38,26 +94,80 @@ macro_rules! call_args {
     }};
 }
 
+/// Borrow arguments for `CallBackend::call_args`
+///
+/// Arguments must be places, like variables or fields, temporaries don't live long enough.
+macro_rules! encode_args {
+    () => (CallArgs::new());
+    ($($e:expr), +,) => (encode_args![$($e),*]);
+    ($($e:expr), +) => {{
+        let mut args = CallArgs::new();
+        $(
+            args.push(&$e);
+        )*
+        args
+    }};
+}
+
 impl Session {
     /// Connect to nvim instance via tcp
     pub fn new_tcp(addr: &str) -> Result<Session> {
-        let stream = TcpStream::connect(addr)?;
+        Self::from_tcp_stream(TcpStream::connect(addr)?)
+    }
+
+    /// Create session from already connected tcp stream
+    pub fn from_tcp_stream(stream: TcpStream) -> Result<Session> {
         let read = stream.try_clone()?;
-        Ok(Session {
-            client: ClientConnection::Tcp(Client::new(stream, read)),
-            timeout: Some(Duration::new(5, 0)),
-        })
+        let shutdown = stream.try_clone()?;
+        #[cfg(unix)]
+        let raw_fd = stream.as_raw_fd();
+        let mut session = Self::from_io(stream, read);
+        #[cfg(unix)]
+        {
+            session.raw_fd = Some(raw_fd);
+        }
+        session.client.set_shutdown_hook(move || {
+            shutdown.shutdown(Shutdown::Both).ok();
+        });
+        Ok(session)
     }
 
     #[cfg(unix)]
     /// Connect to nvim instance via unix socket
     pub fn new_unix_socket<P: AsRef<Path>>(path: P) -> Result<Session> {
-        let stream = UnixStream::connect(path)?;
+        Self::from_unix_stream(UnixStream::connect(path)?)
+    }
+
+    #[cfg(unix)]
+    /// Create session from already connected unix socket stream
+    pub fn from_unix_stream(stream: UnixStream) -> Result<Session> {
         let read = stream.try_clone()?;
-        Ok(Session {
-            client: ClientConnection::UnixSocket(Client::new(stream, read)),
+        let shutdown = stream.try_clone()?;
+        let raw_fd = stream.as_raw_fd();
+        let mut session = Self::from_io(stream, read);
+        session.raw_fd = Some(raw_fd);
+        session.client.set_shutdown_hook(move || {
+            shutdown.shutdown(Shutdown::Both).ok();
+        });
+        Ok(session)
+    }
+
+    /// Create session over any transport
+    ///
+    /// Can be used with in-memory pipes, ssh channels, tls streams and so on.
+    pub fn from_io<R, W>(reader: R, writer: W) -> Session
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        Session {
+            client: Client::new(Box::new(reader), Box::new(writer)),
+            child: None,
             timeout: Some(Duration::new(5, 0)),
-        })
+            tmp_dir: None,
+            #[cfg(unix)]
+            raw_fd: None,
+        }
     }
 
     /// Connect to a Neovim instance by spawning a new one.
@@ -76,8 +186,24 @@ impl Session {
 
     /// Connect to a Neovim instance by spawning a new one
     ///
-    /// stdin/stdout settings will be rewrited to `Stdio::piped()`
+    /// stdin/stdout settings will be rewrited to `Stdio::piped()`.
+    /// If stderr is set to `Stdio::piped()` output is forwarded to `log` crate.
     pub fn new_child_cmd(cmd: &mut Command) -> Result<Session> {
+        Self::spawn_child(cmd, StderrMode::Log)
+    }
+
+    /// Connect to a Neovim instance by spawning a new one
+    ///
+    /// stdin/stdout/stderr settings will be rewrited
+    pub fn new_child_cmd_stderr(cmd: &mut Command, stderr: StderrMode) -> Result<Session> {
+        match stderr {
+            StderrMode::Inherit => cmd.stderr(Stdio::inherit()),
+            _ => cmd.stderr(Stdio::piped()),
+        };
+        Self::spawn_child(cmd, stderr)
+    }
+
+    fn spawn_child(cmd: &mut Command, stderr: StderrMode) -> Result<Session> {
         let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
         let stdout = child
             .stdout
@@ -88,20 +214,65 @@ impl Session {
             .take()
             .ok_or_else(|| Error::new(ErrorKind::Other, "Can't open stdin"))?;
 
-        Ok(Session {
-            client: ClientConnection::Child(Client::new(stdout, stdin), child),
-            timeout: Some(Duration::new(5, 0)),
-        })
+        if let Some(pipe) = child.stderr.take() {
+            forward_stderr(pipe, stderr)?;
+        }
+
+        let child = Arc::new(Mutex::new(child));
+        #[cfg(unix)]
+        let raw_fd = stdout.as_raw_fd();
+        let mut session = Self::from_io(stdout, stdin);
+        #[cfg(unix)]
+        {
+            session.raw_fd = Some(raw_fd);
+        }
+        {
+            let child = child.clone();
+            session
+                .client
+                .set_disconnect_context(move || exit_status_context(&child));
+        }
+        {
+            let child = child.clone();
+            session.client.set_shutdown_hook(move || {
+                wait_or_kill(&child, Duration::new(1, 0)).ok();
+            });
+        }
+        session.child = Some(child);
+        Ok(session)
     }
 
     /// Connect to a Neovim instance that spawned this process over stdin/stdout.
     pub fn new_parent() -> Result<Session> {
         use std::io;
 
-        Ok(Session {
-            client: ClientConnection::Parent(Client::new(io::stdin(), io::stdout())),
-            timeout: Some(Duration::new(5, 0)),
-        })
+        // reading from stdin can't be interrupted, so don't wait dispatch thread on shutdown
+        let mut session = Self::from_io(io::stdin(), io::stdout());
+        session.client.set_shutdown_timeout(Duration::new(0, 0));
+        Ok(session)
+    }
+
+    /// Set function, that unblocks reader when session is closed
+    pub(crate) fn set_shutdown_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.client.set_shutdown_hook(hook);
+    }
+
+    /// Write messages on separate thread, that coalesces bursts of async calls
+    ///
+    /// Sync calls and responses to neovim requests are still flushed right away.
+    pub fn start_writer_thread(&mut self, options: &WriterOptions) -> Result<()> {
+        self.client.start_writer_thread(options)
+    }
+
+    /// Set size and nesting limits for incoming messages
+    ///
+    /// Connection is closed when message exceeds limits.
+    /// Must be called before event loop is started.
+    pub fn set_decode_limits(&mut self, limits: DecodeLimits) {
+        self.client.set_decode_limits(limits);
     }
 
     /// Set call timeout
@@ -113,6 +284,18 @@ impl Session {
         self.timeout = None;
     }
 
+    /// Record every message sent and received, to play it later with `Player`
+    ///
+    /// Must be called before event loop is started.
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        self.client.set_tap(recorder.into_tap());
+    }
+
+    /// Notify `observer` about every call, for example to collect `Metrics`
+    pub fn set_observer(&mut self, observer: Arc<dyn Observer>) {
+        self.client.set_observer(observer);
+    }
+
     /// Start processing rpc response and notifications
     pub fn start_event_loop_channel_handler<H>(
         &mut self,
@@ -121,22 +304,7 @@ impl Session {
     where
         H: RequestHandler + Send + 'static,
     {
-        match self.client {
-            ClientConnection::Child(ref mut client, _) => {
-                client.start_event_loop_channel_handler(request_handler)
-            }
-            ClientConnection::Parent(ref mut client) => {
-                client.start_event_loop_channel_handler(request_handler)
-            }
-            ClientConnection::Tcp(ref mut client) => {
-                client.start_event_loop_channel_handler(request_handler)
-            }
-
-            #[cfg(unix)]
-            ClientConnection::UnixSocket(ref mut client) => {
-                client.start_event_loop_channel_handler(request_handler)
-            }
-        }
+        self.client.start_event_loop_channel_handler(request_handler)
     }
 
     /// Start processing rpc response and notifications
@@ -144,76 +312,772 @@ impl Session {
         self.start_event_loop_channel_handler(DefaultHandler())
     }
 
+    /// Start processing rpc response and notifications,
+    /// notifications are sent to bounded channel
+    ///
+    /// `options` set channel capacity and what happens when it is full.
+    pub fn start_event_loop_bounded_channel_handler<H>(
+        &mut self,
+        request_handler: H,
+        options: &ChannelOptions,
+    ) -> NotifyReceiver
+    where
+        H: RequestHandler + Send + 'static,
+    {
+        self.client
+            .start_event_loop_bounded_channel_handler(request_handler, options)
+    }
+
+    /// Start processing rpc response and notifications,
+    /// notifications are sent to bounded channel
+    pub fn start_event_loop_bounded_channel(&mut self, options: &ChannelOptions) -> NotifyReceiver {
+        self.start_event_loop_bounded_channel_handler(DefaultHandler(), options)
+    }
+
     /// Start processing rpc response and notifications
     pub fn start_event_loop_handler<H>(&mut self, handler: H)
     where
         H: Handler + Send + 'static,
     {
-        match self.client {
-            ClientConnection::Child(ref mut client, _) => client.start_event_loop_handler(handler),
-            ClientConnection::Parent(ref mut client) => client.start_event_loop_handler(handler),
-            ClientConnection::Tcp(ref mut client) => client.start_event_loop_handler(handler),
+        self.client.start_event_loop_handler(handler)
+    }
 
-            #[cfg(unix)]
-            ClientConnection::UnixSocket(ref mut client) => {
-                client.start_event_loop_handler(handler)
-            }
-        }
+    /// Start processing rpc response and notifications,
+    /// incoming requests are handled on worker threads
+    ///
+    /// Every worker gets own clone of handler,
+    /// so slow request doesn't delay responses to calls made meanwhile.
+    /// Clones don't see each other changes, see `PoolOptions`.
+    pub fn start_event_loop_pool<H>(&mut self, handler: H, options: &PoolOptions)
+    where
+        H: Handler + Clone + Send + 'static,
+    {
+        self.client.start_event_loop_pool(handler, options)
     }
 
     /// Start processing rpc response and notifications
     pub fn start_event_loop(&mut self) {
-        match self.client {
-            ClientConnection::Child(ref mut client, _) => client.start_event_loop(),
-            ClientConnection::Parent(ref mut client) => client.start_event_loop(),
-            ClientConnection::Tcp(ref mut client) => client.start_event_loop(),
+        self.client.start_event_loop()
+    }
 
-            #[cfg(unix)]
-            ClientConnection::UnixSocket(ref mut client) => client.start_event_loop(),
-        }
+    /// Start processing without dispatch thread
+    ///
+    /// Messages are read and handled on the caller thread only by `process_pending`
+    /// and sync calls, that read messages until response arrives.
+    /// Use `as_raw_fd` to wait for incoming data in external event loop.
+    /// Fails if transport has no file descriptor, see `start_poll_mode_with`.
+    #[cfg(unix)]
+    pub fn start_poll_mode_handler<H>(&mut self, handler: H) -> Result<()>
+    where
+        H: Handler + Send + 'static,
+    {
+        let raw_fd = self.raw_fd.ok_or_else(|| {
+            Error::new(ErrorKind::Other, "Session transport has no file descriptor")
+        })?;
+        self.client
+            .start_poll_handler(handler, poll::fd_ready(raw_fd));
+        Ok(())
+    }
+
+    /// Start processing without dispatch thread, see `start_poll_mode_handler`
+    #[cfg(unix)]
+    pub fn start_poll_mode(&mut self) -> Result<()> {
+        self.start_poll_mode_handler(DefaultHandler())
+    }
+
+    /// Start processing without dispatch thread with custom readiness check
+    ///
+    /// `ready` waits up to given timeout, or without limit for `None`,
+    /// and returns `true` if transport has data to read.
+    pub fn start_poll_mode_with<H, F>(&mut self, handler: H, ready: F)
+    where
+        H: Handler + Send + 'static,
+        F: FnMut(Option<Duration>) -> Result<bool> + Send + 'static,
+    {
+        self.client.start_poll_handler(handler, Box::new(ready));
+    }
+
+    /// Read available messages and dispatch them on current thread
+    ///
+    /// Must be called when file descriptor is readable in poll mode.
+    /// Returns number of processed messages.
+    pub fn process_pending(&mut self) -> result::Result<usize, CallError> {
+        self.client.process_pending()
+    }
+
+    /// File descriptor, that becomes readable when messages arrive
+    ///
+    /// `None` for sessions created by `from_io`.
+    #[cfg(unix)]
+    pub fn as_raw_fd(&self) -> Option<RawFd> {
+        self.raw_fd
     }
 
     /// Sync call. Call can be made only after event loop begin processing
-    pub fn call(&mut self, method: &str, args: Vec<Value>) -> result::Result<Value, Value> {
-        match self.client {
-            ClientConnection::Child(ref mut client, _) => client.call(method, args, self.timeout),
-            ClientConnection::Parent(ref mut client) => client.call(method, args, self.timeout),
-            ClientConnection::Tcp(ref mut client) => client.call(method, args, self.timeout),
+    pub fn call(&mut self, method: &str, args: Vec<Value>) -> result::Result<Value, CallError> {
+        self.client.call(method, args, self.timeout)
+    }
 
-            #[cfg(unix)]
-            ClientConnection::UnixSocket(ref mut client) => client.call(method, args, self.timeout),
-        }
+    /// Sync call with borrowed arguments, written without converting them to `Value`
+    pub fn call_args(
+        &mut self,
+        method: &str,
+        args: &CallArgs,
+    ) -> result::Result<Value, CallError> {
+        self.client.call_args(method, args, self.timeout)
+    }
+
+    /// Sync call with own timeout, `None` waits without limit
+    ///
+    /// Response, that arrives after timeout, is ignored.
+    pub fn call_with_timeout(
+        &mut self,
+        method: &str,
+        args: Vec<Value>,
+        timeout: Option<Duration>,
+    ) -> result::Result<Value, CallError> {
+        self.client.call(method, args, timeout)
+    }
+
+    /// Connection is lost or session is shut down
+    ///
+    /// All calls made after disconnect fail with `CallError::Disconnected`.
+    pub fn is_disconnected(&self) -> bool {
+        self.client.is_disconnected()
     }
 
     /// Create async call will be executed when only after call() function.
-    pub fn call_async<R: rpc::FromVal<Value>>(
+    pub fn call_async<R: rpc::TryFromVal<Value>>(
         &mut self,
         method: &str,
         args: Vec<Value>,
     ) -> AsyncCall<R> {
-        AsyncCall::new(&mut self.client, method.to_owned(), args)
+        AsyncCall::new(self, method.to_owned(), args)
     }
 
     /// Wait dispatch thread to finish.
     ///
     /// This can happens in case child process connection is lost for some reason.
-    pub fn take_dispatch_guard(&mut self) -> JoinHandle<()> {
-        match self.client {
-            ClientConnection::Child(ref mut client, _) => client.take_dispatch_guard(),
-            ClientConnection::Parent(ref mut client) => client.take_dispatch_guard(),
-            ClientConnection::Tcp(ref mut client) => client.take_dispatch_guard(),
+    /// Returns `None` if event loop is not started or guard is already taken.
+    pub fn take_dispatch_guard(&mut self) -> Option<JoinHandle<()>> {
+        self.client.take_dispatch_guard()
+    }
 
-            #[cfg(unix)]
-            ClientConnection::UnixSocket(ref mut client) => client.take_dispatch_guard(),
+    /// Close connection and stop dispatch thread
+    ///
+    /// Pending calls are finished with error, child process is given
+    /// a second to exit after its stdin is closed and then killed.
+    /// Same happens when session is dropped.
+    pub fn shutdown(&mut self) {
+        self.client.shutdown();
+    }
+
+    /// Process id of child neovim, if session is created by spawning new instance
+    pub fn child_id(&self) -> Option<u32> {
+        self.child.as_ref().map(|child| child.lock().unwrap().id())
+    }
+
+    /// Check exit status of child process without blocking
+    pub fn try_wait_child(&mut self) -> Result<Option<ExitStatus>> {
+        self.child()?.lock().unwrap().try_wait()
+    }
+
+    /// Wait child process to exit
+    pub fn wait_child(&mut self) -> Result<ExitStatus> {
+        let child = self.child()?;
+        poll_exit(&child, None).map(|status| status.expect("No exit status without timeout"))
+    }
+
+    /// Kill child process
+    pub fn kill_child(&mut self) -> Result<()> {
+        self.child()?.lock().unwrap().kill()
+    }
+
+    /// Ask child process to quit without saving, kill it if it still running after timeout
+    ///
+    /// Quit command is sent only when event loop is started.
+    pub fn quit_child(&mut self, timeout: Duration) -> Result<ExitStatus> {
+        let child = self.child()?;
+
+        self.client
+            .call_async("nvim_command".to_owned(), call_args!("qa!"), None);
+
+        wait_or_kill(&child, timeout)
+    }
+
+    fn child(&self) -> Result<Arc<Mutex<Child>>> {
+        self.child
+            .clone()
+            .ok_or_else(|| Error::new(ErrorKind::Other, "Session has no child process"))
+    }
+}
+
+fn forward_stderr(pipe: ChildStderr, mode: StderrMode) -> Result<()> {
+    thread::Builder::new()
+        .name("nvim-stderr".to_owned())
+        .spawn(move || {
+            for line in BufReader::new(pipe).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => return,
+                };
+                match mode {
+                    StderrMode::Callback(ref cb) => cb(&line),
+                    _ => warn!("nvim: {}", line),
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// Wait child process to exit, kill it after timeout
+/// Poll exit status with growing interval, `None` when child is still running after `timeout`
+///
+/// Lock is not held between polls, so other threads can check or kill the child.
+/// Status is cached by `Child` once it's taken.
+fn poll_exit(child: &Mutex<Child>, timeout: Option<Duration>) -> Result<Option<ExitStatus>> {
+    let instant = Instant::now();
+    let mut delay = Duration::from_millis(1);
+
+    loop {
+        if let Some(status) = child.lock().unwrap().try_wait()? {
+            return Ok(Some(status));
         }
+        let mut sleep = delay;
+        if let Some(timeout) = timeout {
+            match timeout.checked_sub(instant.elapsed()) {
+                Some(left) if left > Duration::new(0, 0) => sleep = sleep.min(left),
+                _ => return Ok(None),
+            }
+        }
+        thread::sleep(sleep);
+        delay = (delay * 2).min(Duration::from_millis(50));
     }
 }
 
-pub enum ClientConnection {
-    Child(Client<ChildStdout, ChildStdin>, Child),
-    Parent(Client<Stdin, Stdout>),
-    Tcp(Client<TcpStream, TcpStream>),
+fn wait_or_kill(child: &Mutex<Child>, timeout: Duration) -> Result<ExitStatus> {
+    if let Some(status) = poll_exit(child, Some(timeout))? {
+        return Ok(status);
+    }
 
-    #[cfg(unix)]
-    UnixSocket(Client<UnixStream, UnixStream>),
+    let mut child = child.lock().unwrap();
+    child.kill()?;
+    child.wait()
+}
+
+/// Exit status of child process, waits a bit as process can close stdout before exit
+fn exit_status_context(child: &Mutex<Child>) -> Option<String> {
+    match poll_exit(child, Some(Duration::from_millis(100))) {
+        Ok(Some(status)) => Some(format!("nvim exited with {}", status)),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use rpc::handler::Responder;
+    use rpc::model::{self, RpcMessage};
+
+    #[test]
+    fn test_from_io() {
+        let (stream, mut nvim) = UnixStream::pair().unwrap();
+        let read = stream.try_clone().unwrap();
+
+        let nvim_thread = thread::spawn(move || {
+            let msgid = match model::decode(&mut nvim).unwrap() {
+                RpcMessage::RpcRequest { msgid, method, .. } => {
+                    assert_eq!("nvim_get_mode", method);
+                    msgid
+                }
+                msg => panic!("Unexpected message {:?}", msg),
+            };
+            model::encode(
+                &mut nvim,
+                RpcMessage::RpcResponse {
+                    msgid,
+                    error: Value::Nil,
+                    result: Value::from("n"),
+                },
+            )
+            .unwrap();
+        });
+
+        let mut session = Session::from_io(read, stream);
+        session.start_event_loop();
+        assert_eq!(Ok(Value::from("n")), session.call("nvim_get_mode", vec![]));
+        nvim_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_malformed_message() {
+        let (stream, mut nvim) = UnixStream::pair().unwrap();
+        let read = stream.try_clone().unwrap();
+
+        let nvim_thread = thread::spawn(move || {
+            model::decode(&mut nvim).unwrap();
+            // response without result field
+            nvim.write_all(&[0x93, 0x01, 0x00, 0xc0]).unwrap();
+        });
+
+        let mut session = Session::from_io(read, stream);
+        session.start_event_loop();
+        match session.call("nvim_get_mode", vec![]) {
+            Err(CallError::Disconnected(err)) => {
+                assert!(err.contains("Wrong number of rpc message fields 3"), "{}", err)
+            }
+            res => panic!("Unexpected result {:?}", res),
+        }
+        nvim_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_child_exit() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let cb_lines = lines.clone();
+
+        let mut session = Session::new_child_cmd_stderr(
+            Command::new("sh").args(&["-c", "echo failed >&2; head -c 1 >/dev/null; exit 3"]),
+            StderrMode::Callback(Arc::new(move |line| {
+                cb_lines.lock().unwrap().push(line.to_owned())
+            })),
+        )
+        .unwrap();
+        session.start_event_loop();
+
+        match session.call("nvim_get_mode", vec![]) {
+            Err(CallError::Disconnected(err)) => assert!(err.contains("exit status: 3"), "{}", err),
+            res => panic!("Unexpected result {:?}", res),
+        }
+        assert_eq!(Some(3), session.wait_child().unwrap().code());
+
+        session.take_dispatch_guard().unwrap().join().unwrap();
+        assert_eq!(vec!["failed".to_owned()], *lines.lock().unwrap());
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let (stream, mut nvim) = UnixStream::pair().unwrap();
+
+        let mut session = Session::from_unix_stream(stream).unwrap();
+        session.start_event_loop();
+
+        let (sender, receiver) = mpsc::channel();
+        session
+            .call_async::<Value>("nvim_get_mode", vec![])
+            .cb(move |res| sender.send(res).unwrap())
+            .call();
+        assert!(model::decode(&mut nvim).is_ok());
+
+        session.shutdown();
+
+        let closed = Err(CallError::Disconnected("Session closed".to_owned()));
+        assert_eq!(closed, receiver.recv().unwrap());
+        assert!(session.take_dispatch_guard().is_none());
+        assert!(session.is_disconnected());
+        assert_eq!(closed, session.call("nvim_get_mode", vec![]));
+        assert!(model::decode(&mut nvim).is_err());
+    }
+
+    #[test]
+    fn test_drop() {
+        let (stream, mut nvim) = UnixStream::pair().unwrap();
+
+        let mut session = Session::from_unix_stream(stream).unwrap();
+        session.start_event_loop();
+        drop(session);
+
+        assert!(model::decode(&mut nvim).is_err());
+    }
+
+    struct BrokenWriter {
+        writes_left: usize,
+    }
+
+    impl Write for BrokenWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            if self.writes_left == 0 {
+                return Err(Error::new(ErrorKind::BrokenPipe, "broken pipe"));
+            }
+            self.writes_left -= 1;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_error() {
+        let (stream, _nvim) = UnixStream::pair().unwrap();
+
+        let mut session = Session::from_io(stream, BrokenWriter { writes_left: 1 });
+        session.start_event_loop();
+
+        let (sender, receiver) = mpsc::channel();
+        session
+            .call_async::<Value>("nvim_get_mode", vec![])
+            .cb(move |res| sender.send(res).unwrap())
+            .call();
+        assert!(!session.is_disconnected());
+
+        let err = session.call("nvim_get_mode", vec![]).unwrap_err();
+        match err {
+            CallError::Disconnected(ref msg) => {
+                assert!(msg.starts_with("Error sending message"), "{}", msg)
+            }
+            ref err => panic!("Unexpected error {:?}", err),
+        }
+        assert_eq!(Err(err.clone()), receiver.recv().unwrap());
+        assert!(session.is_disconnected());
+        assert_eq!(Err(err), session.call("nvim_get_mode", vec![]));
+    }
+
+    #[derive(Clone)]
+    struct SlowHandler {
+        release: Arc<Mutex<mpsc::Receiver<()>>>,
+    }
+
+    impl RequestHandler for SlowHandler {
+        fn handle_request(
+            &mut self,
+            name: &str,
+            _args: Vec<Value>,
+        ) -> result::Result<Value, Value> {
+            if name == "slow" {
+                self.release.lock().unwrap().recv().unwrap();
+            }
+            Ok(Value::from(name))
+        }
+    }
+
+    impl Handler for SlowHandler {}
+
+    #[test]
+    fn test_pool() {
+        let (stream, mut nvim) = UnixStream::pair().unwrap();
+        let (release, receiver) = mpsc::channel();
+
+        let mut session = Session::from_unix_stream(stream).unwrap();
+        session.start_event_loop_pool(
+            SlowHandler {
+                release: Arc::new(Mutex::new(receiver)),
+            },
+            PoolOptions::new().set_workers(2),
+        );
+
+        for &(msgid, method) in &[(1, "slow"), (2, "fast")] {
+            model::encode(
+                &mut nvim,
+                RpcMessage::RpcRequest {
+                    msgid,
+                    method: method.to_owned(),
+                    params: vec![],
+                },
+            )
+            .unwrap();
+        }
+
+        let response = |msgid: u64, method: &str| RpcMessage::RpcResponse {
+            msgid,
+            error: Value::Nil,
+            result: Value::from(method),
+        };
+        assert_eq!(response(2, "fast"), model::decode(&mut nvim).unwrap());
+        release.send(()).unwrap();
+        assert_eq!(response(1, "slow"), model::decode(&mut nvim).unwrap());
+    }
+
+    struct DeferredHandler;
+
+    impl RequestHandler for DeferredHandler {
+        fn handle_request_deferred(&mut self, name: &str, _args: Vec<Value>, responder: Responder) {
+            if name == "later" {
+                thread::spawn(move || responder.respond(Ok(Value::from("done"))));
+            }
+        }
+    }
+
+    impl Handler for DeferredHandler {}
+
+    #[test]
+    fn test_deferred_response() {
+        let (stream, mut nvim) = UnixStream::pair().unwrap();
+
+        let mut session = Session::from_unix_stream(stream).unwrap();
+        session.start_event_loop_handler(DeferredHandler);
+
+        for &(msgid, method) in &[(1, "later"), (2, "dropped")] {
+            model::encode(
+                &mut nvim,
+                RpcMessage::RpcRequest {
+                    msgid,
+                    method: method.to_owned(),
+                    params: vec![],
+                },
+            )
+            .unwrap();
+        }
+
+        let mut responses = vec![
+            model::decode(&mut nvim).unwrap(),
+            model::decode(&mut nvim).unwrap(),
+        ];
+        responses.sort_by_key(|msg| match *msg {
+            RpcMessage::RpcResponse { msgid, .. } => msgid,
+            _ => panic!("Unexpected message {:?}", msg),
+        });
+        assert_eq!(
+            vec![
+                RpcMessage::RpcResponse {
+                    msgid: 1,
+                    error: Value::Nil,
+                    result: Value::from("done"),
+                },
+                RpcMessage::RpcResponse {
+                    msgid: 2,
+                    error: Value::from("Request dropped without response"),
+                    result: Value::Nil,
+                },
+            ],
+            responses
+        );
+    }
+
+    struct NestedCallHandler {
+        session: Arc<Mutex<Option<Session>>>,
+        notifications: mpsc::Sender<String>,
+    }
+
+    impl RequestHandler for NestedCallHandler {
+        fn handle_request(
+            &mut self,
+            _name: &str,
+            _args: Vec<Value>,
+        ) -> result::Result<Value, Value> {
+            let mut session = self.session.lock().unwrap();
+            session
+                .as_mut()
+                .unwrap()
+                .call("nvim_get_mode", vec![])
+                .map_err(|e| Value::from(e.to_string()))
+        }
+    }
+
+    impl Handler for NestedCallHandler {
+        fn handle_notify(&mut self, name: &str, _args: Vec<Value>) {
+            self.notifications.send(name.to_owned()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_nested_call() {
+        let (stream, mut nvim) = UnixStream::pair().unwrap();
+        let (sender, notifications) = mpsc::channel();
+
+        let shared = Arc::new(Mutex::new(None));
+        let mut session = Session::from_unix_stream(stream).unwrap();
+        session.set_infinity_timeout();
+        session.start_event_loop_handler(NestedCallHandler {
+            session: shared.clone(),
+            notifications: sender,
+        });
+        *shared.lock().unwrap() = Some(session);
+
+        let request = |msgid| RpcMessage::RpcRequest {
+            msgid,
+            method: "plugin_request".to_owned(),
+            params: vec![],
+        };
+        model::encode(&mut nvim, request(1)).unwrap();
+
+        let msgid = match model::decode(&mut nvim).unwrap() {
+            RpcMessage::RpcRequest { msgid, method, .. } => {
+                assert_eq!("nvim_get_mode", method);
+                msgid
+            }
+            msg => panic!("Unexpected message {:?}", msg),
+        };
+        model::encode(
+            &mut nvim,
+            RpcMessage::RpcNotification {
+                method: "event".to_owned(),
+                params: vec![],
+            },
+        )
+        .unwrap();
+        model::encode(
+            &mut nvim,
+            RpcMessage::RpcResponse {
+                msgid,
+                error: Value::Nil,
+                result: Value::from("n"),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            RpcMessage::RpcResponse {
+                msgid: 1,
+                error: Value::Nil,
+                result: Value::from("n"),
+            },
+            model::decode(&mut nvim).unwrap()
+        );
+        assert_eq!("event", notifications.recv().unwrap());
+
+        let session = shared.lock().unwrap().take();
+        drop(session);
+    }
+
+    #[test]
+    fn test_nested_call_timeout() {
+        let (stream, mut nvim) = UnixStream::pair().unwrap();
+        let (sender, _notifications) = mpsc::channel();
+
+        let shared = Arc::new(Mutex::new(None));
+        let mut session = Session::from_unix_stream(stream).unwrap();
+        session.set_timeout(Duration::from_millis(100));
+        session.start_event_loop_handler(NestedCallHandler {
+            session: shared.clone(),
+            notifications: sender,
+        });
+        *shared.lock().unwrap() = Some(session);
+
+        model::encode(
+            &mut nvim,
+            RpcMessage::RpcRequest {
+                msgid: 1,
+                method: "plugin_request".to_owned(),
+                params: vec![],
+            },
+        )
+        .unwrap();
+
+        // nested nvim_get_mode is never answered
+        match model::decode(&mut nvim).unwrap() {
+            RpcMessage::RpcRequest { method, .. } => assert_eq!("nvim_get_mode", method),
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+        let error = match model::decode(&mut nvim).unwrap() {
+            RpcMessage::RpcResponse { error, .. } => error,
+            msg => panic!("Unexpected message {:?}", msg),
+        };
+        assert_eq!(
+            Value::from("Unknown error type: Wait timeout (nvim_get_mode)"),
+            error
+        );
+
+        let session = shared.lock().unwrap().take();
+        drop(session);
+    }
+
+    #[test]
+    fn test_poll_mode() {
+        let (stream, mut nvim) = UnixStream::pair().unwrap();
+        let (sender, notifications) = mpsc::channel();
+
+        let mut session = Session::from_unix_stream(stream).unwrap();
+        session
+            .start_poll_mode_handler(NestedCallHandler {
+                session: Arc::new(Mutex::new(None)),
+                notifications: sender,
+            })
+            .unwrap();
+        assert!(session.as_raw_fd().is_some());
+        assert_eq!(Ok(0), session.process_pending());
+
+        let nvim_thread = thread::spawn(move || {
+            let msgid = match model::decode(&mut nvim).unwrap() {
+                RpcMessage::RpcRequest { msgid, .. } => msgid,
+                msg => panic!("Unexpected message {:?}", msg),
+            };
+            model::encode(
+                &mut nvim,
+                RpcMessage::RpcNotification {
+                    method: "event".to_owned(),
+                    params: vec![],
+                },
+            )
+            .unwrap();
+            model::encode(
+                &mut nvim,
+                RpcMessage::RpcResponse {
+                    msgid,
+                    error: Value::Nil,
+                    result: Value::from("n"),
+                },
+            )
+            .unwrap();
+            nvim
+        });
+
+        assert_eq!(Ok(Value::from("n")), session.call("nvim_get_mode", vec![]));
+        assert!(notifications.try_recv().is_err());
+        assert_eq!(Ok(1), session.process_pending());
+        assert_eq!("event", notifications.try_recv().unwrap());
+
+        drop(nvim_thread.join().unwrap());
+        match session.process_pending() {
+            Err(CallError::Disconnected(_)) => (),
+            res => panic!("Unexpected result {:?}", res),
+        }
+    }
+
+    fn request_msgid(nvim: &mut UnixStream) -> u64 {
+        match model::decode(nvim).unwrap() {
+            RpcMessage::RpcRequest { msgid, .. } => msgid,
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+    }
+
+    fn respond(nvim: &mut UnixStream, msgid: u64) {
+        model::encode(
+            nvim,
+            RpcMessage::RpcResponse {
+                msgid,
+                error: Value::Nil,
+                result: Value::from("n"),
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_timeout_and_cancel() {
+        let (stream, mut nvim) = UnixStream::pair().unwrap();
+
+        let mut session = Session::from_unix_stream(stream).unwrap();
+        session.start_event_loop();
+
+        assert_eq!(
+            Err(CallError::GenericError(
+                "Wait timeout (nvim_get_mode)".to_owned()
+            )),
+            session.call_with_timeout("nvim_get_mode", vec![], Some(Duration::from_millis(10)))
+        );
+        let timed_out = request_msgid(&mut nvim);
+
+        let (sender, receiver) = mpsc::channel();
+        let handle = session
+            .call_async::<Value>("nvim_get_mode", vec![])
+            .cb(move |res| sender.send(res).unwrap())
+            .call();
+        let cancelled = request_msgid(&mut nvim);
+        assert!(handle.cancel());
+
+        respond(&mut nvim, timed_out);
+        respond(&mut nvim, cancelled);
+        respond(&mut nvim, 1000);
+
+        let nvim_thread = thread::spawn(move || {
+            let msgid = request_msgid(&mut nvim);
+            respond(&mut nvim, msgid);
+            nvim
+        });
+        assert_eq!(Ok(Value::from("n")), session.call("nvim_get_mode", vec![]));
+        assert!(receiver.try_recv().is_err());
+        nvim_thread.join().unwrap();
+    }
 }
@@ -0,0 +1,628 @@
+//! Read and write very large buffers in chunks
+//!
+//! Every chunk is requested together with `b:changedtick`,
+//! so an edit made by somebody else between chunks is detected.
+use rmpv::Value;
+
+use neovim::{try_map_result, CallBackend, CallError};
+use neovim_api::Buffer;
+use rpc::model::{self, IntoVal, TryFromVal};
+use rpc::CallArgs;
+
+/// Options for `Buffer::read_chunks` and `Buffer::write_chunks`
+#[derive(Debug, Clone)]
+pub struct ChunkOptions {
+    chunk_size: usize,
+    check_changedtick: bool,
+}
+
+impl ChunkOptions {
+    pub fn new() -> ChunkOptions {
+        ChunkOptions {
+            chunk_size: 10_000,
+            check_changedtick: true,
+        }
+    }
+
+    /// Lines per request, 10000 by default
+    pub fn set_chunk_size(&mut self, chunk_size: usize) -> &mut Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Fail when buffer is changed by somebody else between chunks, `true` by default
+    pub fn set_check_changedtick(&mut self, check: bool) -> &mut Self {
+        self.check_changedtick = check;
+        self
+    }
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        ChunkOptions::new()
+    }
+}
+
+/// Lines of buffer, starting at 0-based line `start`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub start: i64,
+    pub lines: Vec<Vec<u8>>,
+}
+
+fn changed_error(action: &str) -> CallError {
+    CallError::GenericError(format!("Buffer changed while {}", action))
+}
+
+/// Message of exception, thrown when changedtick doesn't match before write
+const CHANGED_MARK: &str = "neovim-lib: buffer changed";
+
+/// Write one chunk inside of target buffer, so `undojoin` applies to it
+///
+/// Arguments are buffer, start, end, lines, join and expected changedtick,
+/// `-1` skips changedtick check. Returns absolute start and new changedtick.
+const WRITE_CHUNK: &str = r#"
+local buf, start, finish, lines, join, tick = ...
+if tick >= 0 and vim.api.nvim_buf_get_changedtick(buf) ~= tick then
+  error('neovim-lib: buffer changed')
+end
+local count = vim.api.nvim_buf_line_count(buf)
+local function absolute(index)
+  if index < 0 then
+    index = count + 1 + index
+  end
+  return math.max(0, math.min(index, count))
+end
+start = absolute(start)
+finish = math.max(start, absolute(finish))
+vim.api.nvim_buf_call(buf, function()
+  if join then
+    vim.cmd('undojoin')
+  end
+  vim.api.nvim_buf_set_lines(buf, start, finish, true, lines)
+end)
+return {start, vim.api.nvim_buf_get_changedtick(buf)}
+"#;
+
+/// Index in `get_lines` convention to line number in `0..=count`
+fn absolute(index: i64, count: i64) -> i64 {
+    let index = if index < 0 { count + 1 + index } else { index };
+    index.max(0).min(count)
+}
+
+fn atomic_call(method: &str, args: Vec<Value>) -> Value {
+    Value::from(vec![Value::from(method), Value::from(args)])
+}
+
+/// Convert next result of batch, missing or unexpected value is an error
+fn next_result<T: TryFromVal<Value>>(val: Option<Value>) -> Result<T, CallError> {
+    match val {
+        Some(val) => try_map_result(val),
+        None => Err(CallError::GenericError("Missing result".to_owned())),
+    }
+}
+
+/// Make calls in one batch, fail on first error
+fn call_atomic<B: CallBackend>(neovim: &mut B, calls: Vec<Value>) -> Result<Vec<Value>, CallError> {
+    let res: Vec<Value> = neovim
+        .call_args("nvim_call_atomic", &encode_args![calls])
+        .and_then(try_map_result)?;
+    let mut res = res.into_iter();
+    let results = next_result(res.next())?;
+
+    match res.next() {
+        Some(Value::Array(err)) => {
+            let kind = err.get(1).and_then(Value::as_i64).unwrap_or(0);
+            // message may quote non utf-8 line
+            let msg = match err.get(2).and_then(Value::as_slice) {
+                Some(msg) => model::utf8_lossy(msg.to_vec()),
+                None => String::new(),
+            };
+            Err(CallError::NeovimError(kind, msg))
+        }
+        _ => Ok(results),
+    }
+}
+
+/// Iterator over buffer chunks, see `Buffer::read_chunks`
+pub struct LineChunks<'a, B: CallBackend + 'a> {
+    buffer: &'a Buffer,
+    neovim: &'a mut B,
+    options: ChunkOptions,
+    next: i64,
+    end: i64,
+    changedtick: Option<i64>,
+    done: bool,
+}
+
+impl<'a, B: CallBackend + 'a> LineChunks<'a, B> {
+    fn read(&mut self) -> Result<Option<Chunk>, CallError> {
+        let buffer = self.buffer.get_value();
+        if self.changedtick.is_none() {
+            let calls = vec![
+                atomic_call("nvim_buf_line_count", vec![buffer.clone()]),
+                atomic_call("nvim_buf_get_changedtick", vec![buffer.clone()]),
+            ];
+            let mut res = call_atomic(&mut *self.neovim, calls)?.into_iter();
+            let count = next_result(res.next())?;
+            self.changedtick = Some(next_result(res.next())?);
+            self.next = absolute(self.next, count);
+            self.end = absolute(self.end, count);
+        }
+        if self.next >= self.end {
+            return Ok(None);
+        }
+
+        let start = self.next;
+        let end = (start + self.options.chunk_size as i64).min(self.end);
+        let calls = vec![
+            atomic_call(
+                "nvim_buf_get_lines",
+                vec![
+                    buffer.clone(),
+                    start.into_val(),
+                    end.into_val(),
+                    false.into_val(),
+                ],
+            ),
+            atomic_call("nvim_buf_get_changedtick", vec![buffer.clone()]),
+        ];
+        let mut res = call_atomic(&mut *self.neovim, calls)?.into_iter();
+        let lines: Vec<Vec<u8>> = next_result(res.next())?;
+        let changedtick = Some(next_result(res.next())?);
+        if self.options.check_changedtick && self.changedtick != changedtick {
+            return Err(changed_error("reading"));
+        }
+        self.changedtick = changedtick;
+
+        if lines.is_empty() {
+            return Ok(None);
+        }
+        self.next = end;
+        Ok(Some(Chunk { start, lines }))
+    }
+}
+
+impl<'a, B: CallBackend + 'a> Iterator for LineChunks<'a, B> {
+    type Item = Result<Chunk, CallError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read() {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl Buffer {
+    /// Read lines `start..end` in chunks, instead of one huge response
+    ///
+    /// Indexing is same as in `get_lines`, `-1` is end of buffer.
+    /// Iteration stops with error when buffer is changed between chunks.
+    pub fn read_chunks<'a, B: CallBackend>(
+        &'a self,
+        neovim: &'a mut B,
+        start: i64,
+        end: i64,
+        options: &ChunkOptions,
+    ) -> LineChunks<'a, B> {
+        LineChunks {
+            buffer: self,
+            neovim,
+            options: options.clone(),
+            next: start,
+            end,
+            changedtick: None,
+            done: false,
+        }
+    }
+
+    /// Replace lines `start..end` with `replacement`, sent in chunks
+    ///
+    /// Indexing is same as in `set_lines`. Chunks are joined with `undojoin` inside
+    /// of the buffer, so whole replacement is undone at once, requires neovim 0.5.
+    /// When buffer is changed by somebody else between chunks, or undo block can't
+    /// be joined, writing stops with error and already written chunks are kept.
+    /// Returns number of written lines.
+    pub fn write_chunks<B, I>(
+        &self,
+        neovim: &mut B,
+        start: i64,
+        end: i64,
+        replacement: I,
+        options: &ChunkOptions,
+    ) -> Result<usize, CallError>
+    where
+        B: CallBackend,
+        I: IntoIterator<Item = Vec<u8>>,
+    {
+        let buffer = self.get_value();
+        let mut lines = replacement.into_iter();
+        let mut written = 0;
+        // absolute start and changedtick after previous chunk
+        let mut state: Option<(i64, i64)> = None;
+
+        loop {
+            let chunk: Vec<Vec<u8>> = lines.by_ref().take(options.chunk_size).collect();
+            if chunk.is_empty() && state.is_some() {
+                return Ok(written);
+            }
+            let chunk_len = chunk.len();
+            let chunk: Vec<Value> = chunk.into_iter().map(Value::Binary).collect();
+
+            let args: Vec<Value> = match state {
+                None => vec![
+                    buffer.clone(),
+                    start.into_val(),
+                    end.into_val(),
+                    chunk.into_val(),
+                    false.into_val(),
+                    (-1).into_val(),
+                ],
+                Some((start, tick)) => {
+                    let at = start + written as i64;
+                    let tick = if options.check_changedtick { tick } else { -1 };
+                    vec![
+                        buffer.clone(),
+                        at.into_val(),
+                        at.into_val(),
+                        chunk.into_val(),
+                        true.into_val(),
+                        tick.into_val(),
+                    ]
+                }
+            };
+
+            let res: Vec<i64> = match neovim
+                .call_args("nvim_exec_lua", &encode_args![WRITE_CHUNK, args])
+                .and_then(try_map_result)
+            {
+                Err(CallError::NeovimError(_, ref msg)) if msg.contains(CHANGED_MARK) => {
+                    return Err(changed_error("writing"));
+                }
+                res => res?,
+            };
+            if res.len() != 2 {
+                return Err(CallError::GenericError(format!(
+                    "Unexpected chunk write result {:?}",
+                    res
+                )));
+            }
+            let start = state.map_or(res[0], |(start, _)| start);
+            state = Some((start, res[1]));
+            written += chunk_len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockNeovim;
+    use neovim::Neovim;
+    use rpc::model::FromVal;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct FakeBuffer {
+        lines: Vec<Value>,
+        changedtick: i64,
+        /// Changedtick is bumped before this request
+        edit_before: Option<usize>,
+        requests: usize,
+        /// Undo blocks, created by writes
+        undo_blocks: usize,
+        /// Last change is undone, so `undojoin` fails
+        undone: bool,
+    }
+
+    fn absolute_range(args: &[Value], len: usize) -> (usize, usize) {
+        let len = len as i64;
+        let start = absolute(args[1].as_i64().unwrap(), len);
+        let end = absolute(args[2].as_i64().unwrap(), len).max(start);
+        (start as usize, end as usize)
+    }
+
+    impl FakeBuffer {
+        fn request(&mut self) {
+            if self.edit_before == Some(self.requests) {
+                self.changedtick += 1;
+            }
+            self.requests += 1;
+        }
+
+        fn call(&mut self, method: &str, args: Vec<Value>) -> Result<Value, String> {
+            match method {
+                "nvim_buf_get_changedtick" => Ok(Value::from(self.changedtick)),
+                "nvim_buf_line_count" => Ok(Value::from(self.lines.len())),
+                "nvim_buf_get_lines" => {
+                    let (start, end) = absolute_range(&args, self.lines.len());
+                    Ok(Value::from(self.lines[start..end].to_vec()))
+                }
+                _ => panic!("Unexpected {}", method),
+            }
+        }
+
+        fn atomic(&mut self, args: Vec<Value>) -> Value {
+            self.request();
+            let mut results = Vec::new();
+            for (i, call) in Vec::<Value>::from_val(args[0].clone())
+                .into_iter()
+                .enumerate()
+            {
+                let call = Vec::<Value>::from_val(call);
+                let method = call[0].as_str().unwrap().to_owned();
+                match self.call(&method, Vec::<Value>::from_val(call[1].clone())) {
+                    Ok(res) => results.push(res),
+                    Err(msg) => {
+                        let err = vec![Value::from(i), Value::from(0), Value::from(msg)];
+                        return Value::from(vec![Value::from(results), Value::from(err)]);
+                    }
+                }
+            }
+            Value::from(vec![Value::from(results), Value::Nil])
+        }
+
+        /// Same as `WRITE_CHUNK`
+        fn write_chunk(&mut self, args: Vec<Value>) -> Result<Value, Value> {
+            self.request();
+            let error = |msg: &str| Err(Value::from(vec![Value::from(0), Value::from(msg)]));
+            assert_eq!(WRITE_CHUNK, args[0].as_str().unwrap());
+            let args = Vec::<Value>::from_val(args[1].clone());
+
+            let tick = args[5].as_i64().unwrap();
+            if tick >= 0 && tick != self.changedtick {
+                return error(&format!("Error executing lua: {}", CHANGED_MARK));
+            }
+            let (start, end) = absolute_range(&args, self.lines.len());
+            if args[4] == Value::from(true) {
+                if self.undone {
+                    return error("E790: undojoin is not allowed after undo");
+                }
+            } else {
+                self.undo_blocks += 1;
+            }
+            let new = Vec::<Value>::from_val(args[3].clone());
+            self.lines.splice(start..end, new);
+            self.changedtick += 1;
+            Ok(Value::from(vec![
+                Value::from(start),
+                Value::from(self.changedtick),
+            ]))
+        }
+    }
+
+    fn start(fake: &Arc<Mutex<FakeBuffer>>) -> (MockNeovim, Neovim) {
+        let (mock, mut session) = MockNeovim::new();
+        let atomic = fake.clone();
+        mock.handle("nvim_call_atomic", move |args| {
+            Ok(atomic.lock().unwrap().atomic(args))
+        });
+        let lua = fake.clone();
+        mock.handle("nvim_exec_lua", move |args| {
+            lua.lock().unwrap().write_chunk(args)
+        });
+        session.start_event_loop();
+        (mock, Neovim::new(session))
+    }
+
+    fn bytes(lines: Vec<Value>) -> Vec<Vec<u8>> {
+        Vec::<Vec<u8>>::from_val(Value::from(lines))
+    }
+
+    fn lines(count: usize) -> Vec<Value> {
+        (0..count).map(|i| Value::from(i.to_string())).collect()
+    }
+
+    fn buffer() -> Buffer {
+        Buffer::new(model::encode_ext_handle(0, 1))
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let fake = Arc::new(Mutex::new(FakeBuffer {
+            lines: lines(10),
+            ..Default::default()
+        }));
+        let (_mock, mut nvim) = start(&fake);
+        let buf = buffer();
+        let mut options = ChunkOptions::new();
+        options.set_chunk_size(4);
+
+        let chunks: Vec<_> = buf
+            .read_chunks(&mut nvim, 1, -1, &options)
+            .map(|chunk| chunk.unwrap())
+            .collect();
+        assert_eq!(
+            vec![1, 5, 9],
+            chunks.iter().map(|chunk| chunk.start).collect::<Vec<_>>()
+        );
+        let all: Vec<_> = chunks.into_iter().flat_map(|chunk| chunk.lines).collect();
+        assert_eq!(bytes(lines(10)[1..].to_vec()), all);
+
+        let chunks: Vec<_> = buf.read_chunks(&mut nvim, 0, -3, &options).collect();
+        assert_eq!(2, chunks.len());
+        assert_eq!(4, chunks[1].as_ref().unwrap().lines.len());
+
+        // same as get_lines(-4, -1)
+        let chunks: Vec<_> = buf
+            .read_chunks(&mut nvim, -4, -1, &options)
+            .map(|chunk| chunk.unwrap())
+            .collect();
+        assert_eq!(
+            vec![Chunk {
+                start: 7,
+                lines: bytes(lines(10)[7..].to_vec()),
+            }],
+            chunks
+        );
+    }
+
+    #[test]
+    fn test_read_detects_change() {
+        let fake = Arc::new(Mutex::new(FakeBuffer {
+            lines: lines(10),
+            edit_before: Some(2),
+            ..Default::default()
+        }));
+        let (_mock, mut nvim) = start(&fake);
+        let buf = buffer();
+        let mut options = ChunkOptions::new();
+        options.set_chunk_size(4);
+
+        let chunks: Vec<_> = buf.read_chunks(&mut nvim, 0, -1, &options).collect();
+        assert_eq!(2, chunks.len());
+        assert!(chunks[0].is_ok());
+        assert_eq!(Err(changed_error("reading")), chunks[1]);
+    }
+
+    fn replacement() -> impl Iterator<Item = Vec<u8>> {
+        (0..5).map(|i| format!("new {}", i).into_bytes())
+    }
+
+    #[test]
+    fn test_write_chunks() {
+        let fake = Arc::new(Mutex::new(FakeBuffer {
+            lines: lines(3),
+            ..Default::default()
+        }));
+        let (_mock, mut nvim) = start(&fake);
+        let buf = buffer();
+        let mut options = ChunkOptions::new();
+        options.set_chunk_size(2);
+
+        assert_eq!(
+            Ok(5),
+            buf.write_chunks(&mut nvim, 1, 2, replacement(), &options)
+        );
+        {
+            let mut fake = fake.lock().unwrap();
+            let expected = vec!["0", "new 0", "new 1", "new 2", "new 3", "new 4", "2"];
+            assert_eq!(
+                bytes(expected.into_iter().map(Value::from).collect()),
+                bytes(fake.lines.clone())
+            );
+            assert_eq!(1, fake.undo_blocks);
+            fake.lines = lines(3);
+        }
+
+        // append after the last line
+        assert_eq!(
+            Ok(5),
+            buf.write_chunks(&mut nvim, -1, -1, replacement(), &options)
+        );
+        let fake = fake.lock().unwrap();
+        let expected: Vec<_> = lines(3)
+            .into_iter()
+            .chain(replacement().map(Value::from))
+            .collect();
+        assert_eq!(bytes(expected), bytes(fake.lines.clone()));
+        assert_eq!(2, fake.undo_blocks);
+    }
+
+    #[test]
+    fn test_write_detects_change() {
+        let fake = Arc::new(Mutex::new(FakeBuffer {
+            lines: lines(3),
+            edit_before: Some(1),
+            ..Default::default()
+        }));
+        let (_mock, mut nvim) = start(&fake);
+        let buf = buffer();
+        let mut options = ChunkOptions::new();
+        options.set_chunk_size(2);
+
+        assert_eq!(
+            Err(changed_error("writing")),
+            buf.write_chunks(&mut nvim, 0, -1, replacement(), &options)
+        );
+        // first chunk is kept
+        assert_eq!(2, fake.lock().unwrap().lines.len());
+    }
+
+    #[test]
+    fn test_write_join_error() {
+        let fake = Arc::new(Mutex::new(FakeBuffer {
+            lines: lines(3),
+            undone: true,
+            ..Default::default()
+        }));
+        let (_mock, mut nvim) = start(&fake);
+        let buf = buffer();
+        let mut options = ChunkOptions::new();
+        options.set_chunk_size(2);
+
+        assert_eq!(
+            Err(CallError::NeovimError(
+                0,
+                "E790: undojoin is not allowed after undo".to_owned()
+            )),
+            buf.write_chunks(&mut nvim, 0, -1, replacement(), &options)
+        );
+    }
+
+    fn assert_generic_error<T: ::std::fmt::Debug>(res: &Result<T, CallError>) {
+        match *res {
+            Err(CallError::GenericError(_)) => (),
+            ref res => panic!("Unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_unexpected_results() {
+        let (mock, mut session) = MockNeovim::new();
+        mock.respond(
+            "nvim_call_atomic",
+            Value::from(vec![Value::from(vec![Value::Nil]), Value::Nil]),
+        )
+        .respond("nvim_exec_lua", Value::from(vec![Value::from(0)]));
+        session.start_event_loop();
+        let mut nvim = Neovim::new(session);
+        let buf = buffer();
+        let options = ChunkOptions::new();
+
+        let chunks: Vec<_> = buf.read_chunks(&mut nvim, 0, -1, &options).collect();
+        assert_eq!(1, chunks.len());
+        assert_generic_error(&chunks[0]);
+
+        let res = buf.write_chunks(&mut nvim, 0, -1, replacement(), &options);
+        assert_generic_error(&res);
+    }
+
+    #[test]
+    fn test_non_utf8_error() {
+        let (mock, mut session) = MockNeovim::new();
+        let err = vec![
+            Value::from(0),
+            Value::from(1),
+            Value::Binary(b"E5108: caf\xe9".to_vec()),
+        ];
+        mock.respond(
+            "nvim_call_atomic",
+            Value::from(vec![Value::from(Vec::<Value>::new()), Value::from(err)]),
+        );
+        session.start_event_loop();
+        let mut nvim = Neovim::new(session);
+
+        let chunks: Vec<_> = buffer()
+            .read_chunks(&mut nvim, 0, -1, &ChunkOptions::new())
+            .collect();
+        assert_eq!(
+            vec![Err(CallError::NeovimError(
+                1,
+                "E5108: caf\u{fffd}".to_owned()
+            ))],
+            chunks
+        );
+    }
+}
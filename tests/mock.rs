@@ -0,0 +1,277 @@
+extern crate neovim_lib;
+
+use std::io::{self, Cursor, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use neovim_lib::neovim::CallError;
+use neovim_lib::{
+    Direction, Metrics, MockNeovim, Neovim, NeovimApi, NeovimApiAsync, NeovimBytesApi, Record,
+    Recorder, RequestHandler, RpcMessage, Value, WriterOptions,
+};
+
+struct EchoHandler;
+
+impl RequestHandler for EchoHandler {
+    fn handle_request(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Value> {
+        match name {
+            "echo" => Ok(Value::from(args)),
+            _ => Err(Value::from("unknown")),
+        }
+    }
+}
+
+#[test]
+fn canned_responses() {
+    let (mock, mut session) = MockNeovim::new();
+    mock.respond("nvim_get_current_buf", Value::Ext(0, vec![1]))
+        .handle("nvim_buf_get_lines", |args| {
+            assert_eq!(Value::from(0), args[1]);
+            Ok(Value::from(vec![
+                Value::from("first"),
+                Value::from("second"),
+            ]))
+        })
+        .respond_err("nvim_command", "E492: Not an editor command");
+    session.start_event_loop();
+    let mut nvim = Neovim::new(session);
+
+    let buf = nvim.get_current_buf().unwrap();
+    assert_eq!(
+        vec!["first".to_owned(), "second".to_owned()],
+        buf.get_lines(&mut nvim, 0, -1, false).unwrap()
+    );
+    assert_eq!(
+        Err(CallError::NeovimError(
+            0,
+            "E492: Not an editor command".to_owned()
+        )),
+        nvim.command("foo")
+    );
+
+    let methods: Vec<_> = mock.calls().into_iter().map(|call| call.method).collect();
+    assert_eq!(
+        vec!["nvim_get_current_buf", "nvim_buf_get_lines", "nvim_command"],
+        methods
+    );
+    assert_eq!(
+        vec![vec![
+            Value::Ext(0, vec![1]),
+            Value::from(0),
+            Value::from(-1),
+            Value::from(false),
+        ]],
+        mock.calls_to("nvim_buf_get_lines")
+    );
+    mock.verify();
+}
+
+#[test]
+#[should_panic(expected = "Unexpected calls: nvim_get_mode")]
+fn unexpected_call() {
+    let (mock, mut session) = MockNeovim::new();
+    session.start_event_loop();
+    let mut nvim = Neovim::new(session);
+
+    assert!(nvim.get_mode().is_err());
+    mock.verify();
+}
+
+#[test]
+#[should_panic(expected = "Expected calls not made: nvim_command, nvim_get_mode")]
+fn unused_expectations() {
+    let (mock, mut session) = MockNeovim::new();
+    mock.respond("nvim_get_mode", Value::from("n"))
+        .respond("nvim_get_current_line", Value::from("line"))
+        .handle("nvim_command", |_| Ok(Value::Nil));
+    session.start_event_loop();
+    let mut nvim = Neovim::new(session);
+
+    nvim.get_current_line().unwrap();
+    mock.verify();
+}
+
+#[test]
+fn notifications_and_requests_to_client() {
+    let (mut mock, mut session) = MockNeovim::new();
+    mock.set_timeout(Duration::new(1, 0));
+    let receiver = session.start_event_loop_channel_handler(EchoHandler);
+
+    mock.notify("buf_changed", vec![Value::from(1)]);
+    assert_eq!(
+        ("buf_changed".to_owned(), vec![Value::from(1)]),
+        receiver.recv_timeout(Duration::new(1, 0)).unwrap()
+    );
+
+    assert_eq!(
+        Ok(Value::from(vec![Value::from("hi")])),
+        mock.request("echo", vec![Value::from("hi")])
+    );
+    assert_eq!(
+        Err(CallError::GenericError("unknown".to_owned())),
+        mock.request("other", vec![])
+    );
+}
+
+#[test]
+fn close_disconnects_session() {
+    let (mock, mut session) = MockNeovim::new();
+    let receiver = session.start_event_loop_channel();
+
+    mock.close();
+    assert!(receiver.recv_timeout(Duration::new(1, 0)).is_err());
+    assert!(session.is_disconnected());
+}
+
+#[test]
+fn writer_thread_keeps_order() {
+    let (mock, mut session) = MockNeovim::new();
+    mock.respond("nvim_call_function", Value::Nil)
+        .respond("nvim_get_mode", Value::Map(vec![]));
+    let mut options = WriterOptions::new();
+    options.set_linger(Duration::from_millis(10));
+    session.start_writer_thread(&options).unwrap();
+    session.start_event_loop();
+    let mut nvim = Neovim::new(session);
+
+    for i in 0..100 {
+        nvim.call_function_async("Highlight", vec![Value::from(i)])
+            .call();
+    }
+    nvim.get_mode().unwrap();
+
+    let calls = mock.calls();
+    assert_eq!(101, calls.len());
+    for (i, call) in calls[..100].iter().enumerate() {
+        assert_eq!(Value::from(vec![Value::from(i)]), call.params[1]);
+    }
+    assert_eq!("nvim_get_mode", calls[100].method);
+    mock.verify();
+}
+
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn request_recorded_before_response() {
+    let (mock, mut session) = MockNeovim::new();
+    mock.respond("nvim_get_current_line", Value::from("line"));
+    let buf = SharedBuf::default();
+    session.set_recorder(Recorder::new(buf.clone()));
+    session.start_event_loop();
+    let mut nvim = Neovim::new(session);
+
+    for _ in 0..50 {
+        nvim.get_current_line().unwrap();
+    }
+
+    let data = buf.0.lock().unwrap().clone();
+    let mut reader = Cursor::new(data);
+    let mut sent = Vec::new();
+    let mut responses = 0;
+    while let Some(record) = Record::read(&mut reader).unwrap() {
+        match (record.direction, record.message) {
+            (Direction::Outgoing, RpcMessage::RpcRequest { msgid, .. }) => sent.push(msgid),
+            (Direction::Incoming, RpcMessage::RpcResponse { msgid, .. }) => {
+                assert!(sent.contains(&msgid), "response {} before request", msgid);
+                responses += 1;
+            }
+            record => panic!("Unexpected record {:?}", record),
+        }
+    }
+    assert_eq!(50, responses);
+}
+
+#[test]
+fn metrics_per_method() {
+    let (mock, mut session) = MockNeovim::new();
+    mock.respond("nvim_get_current_line", Value::from("line"))
+        .respond_err("nvim_command", "E492: Not an editor command");
+    let metrics = Arc::new(Metrics::new());
+    session.set_observer(metrics.clone());
+    session.start_event_loop();
+    let mut nvim = Neovim::new(session);
+
+    nvim.get_current_line().unwrap();
+    nvim.get_current_line().unwrap();
+    nvim.command("foo").unwrap_err();
+
+    let stats = metrics.method("nvim_get_current_line").unwrap();
+    assert_eq!(2, stats.calls);
+    assert_eq!(0, stats.errors);
+    // two 5 byte fixstr "line"
+    assert_eq!(10, stats.response_bytes);
+    // two [0, msgid, "nvim_get_current_line", []] requests, 26 bytes each
+    assert_eq!(52, stats.request_bytes);
+
+    let stats = metrics.method("nvim_command").unwrap();
+    assert_eq!(1, stats.calls);
+    assert_eq!(1.0, stats.error_rate());
+
+    assert_eq!(2, metrics.snapshot().len());
+}
+
+#[test]
+fn non_utf8_lines() {
+    let (mock, mut session) = MockNeovim::new();
+    let latin1 = Value::Binary(b"caf\xe9".to_vec());
+    mock.respond("nvim_get_current_buf", Value::Ext(0, vec![1]))
+        .respond(
+            "nvim_buf_get_lines",
+            Value::from(vec![Value::from("ok"), latin1.clone()]),
+        )
+        .respond("nvim_buf_set_lines", Value::Nil)
+        .respond("nvim_get_current_line", latin1.clone())
+        .respond("nvim_get_var", Value::from(vec![Value::from(1)]));
+    session.start_event_loop();
+    let mut nvim = Neovim::new(session);
+    let buf = nvim.get_current_buf().unwrap();
+
+    assert_eq!(
+        vec![b"ok".to_vec(), b"caf\xe9".to_vec()],
+        buf.get_lines_bytes(&mut nvim, 0, -1, false).unwrap()
+    );
+    assert_eq!(
+        vec!["ok".to_owned(), "caf\u{fffd}".to_owned()],
+        buf.get_lines_lossy(&mut nvim, 0, -1, false).unwrap()
+    );
+    assert!(buf.get_lines_strict(&mut nvim, 0, -1, false).is_err());
+    // plain api neither panics nor silently replaces bytes
+    match buf.get_lines(&mut nvim, 0, -1, false) {
+        Err(CallError::GenericError(msg)) => assert!(msg.contains("utf-8"), "{}", msg),
+        res => panic!("Unexpected result {:?}", res),
+    }
+    assert!(nvim.get_current_line().is_err());
+    // async call reports error to callback, dispatch thread keeps running
+    let (sender, receiver) = mpsc::channel();
+    nvim.get_current_line_async()
+        .cb(move |res| sender.send(res).unwrap())
+        .call();
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        Err(CallError::GenericError(msg)) => assert!(msg.contains("utf-8"), "{}", msg),
+        res => panic!("Unexpected result {:?}", res),
+    }
+    assert_eq!(b"caf\xe9".to_vec(), nvim.get_current_line_bytes().unwrap());
+    match nvim.get_var_bytes("list") {
+        Err(CallError::GenericError(_)) => (),
+        res => panic!("Unexpected result {:?}", res),
+    }
+
+    buf.set_lines_bytes(&mut nvim, 0, 1, false, &[b"\xff".to_vec()])
+        .unwrap();
+    assert_eq!(
+        Value::from(vec![Value::Binary(vec![0xff])]),
+        mock.calls_to("nvim_buf_set_lines")[0][4]
+    );
+    mock.verify();
+}